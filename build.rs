@@ -12,6 +12,7 @@ include!("src/cli/args.rs");
 
 fn write_completions_file<G: Generator + Copy, P: AsRef<OsStr>>(generator: G, out_dir: P) {
     let mut args = Args::command();
+    #[allow(clippy::single_element_loop)]
     for name in &["gem"] {
         clap_complete::generate_to(generator, &mut args, name.to_string(), &out_dir)
             .expect("clap complete generation failed");
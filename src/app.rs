@@ -8,12 +8,12 @@ use {crate::*, anyhow::Result, crokey::CroKey, crossterm::event::Event};
 
 use crate::cli::action::Action;
 use crate::cli::internal::Internal;
-use crate::cli::keybindings::KeyBindings;
 use crate::cli::W;
 use crate::executor::command_output::CommandExecInfo;
-use crate::executor::execution_plan::ExecutionPlan;
+use crate::executor::execution_plan::{ExecutionItem, ExecutionPlan};
 use crate::executor::job_location::JobLocation;
-use crate::executor::Executor;
+use crate::executor::{Executor, ExecutorOptions};
+use crate::report::{timed_out, RunReport};
 use crate::view::View;
 
 /// Run the execution plan
@@ -23,11 +23,12 @@ pub fn run(
     location: JobLocation,
     execution_plan: ExecutionPlan,
     event_source: &EventSource,
-    keep_files: bool,
+    options: ExecutorOptions,
+    report: &mut RunReport,
 ) -> Result<Option<Action>> {
-    let keybindings = KeyBindings::default();
+    let keybindings = view.keybindings().clone();
 
-    let executor = Executor::new(location, execution_plan, keep_files)?;
+    let executor = Executor::new(location, execution_plan, options)?;
 
     view.execution_starts();
     let user_events = event_source.receiver();
@@ -84,14 +85,22 @@ pub fn run(
             //     // }
             // }
             recv(executor.line_receiver) -> info => {
-                match info? {
-                    CommandExecInfo::Line(line) => {
+                let info = info?;
+                report.record(&info);
+                if !report.shows(&info) {
+                    continue;
+                }
+                match info {
+                    CommandExecInfo::Line { chunk, line } => {
                         view.draw_help_line(w)?;
-                        match view.write_command_output(w, line.content) {
+                        match view.write_chunk_output(w, &chunk.id, line.content) {
                             Ok(_) => debug!("Output written"),
                             Err(e) => error!("Error on output: {}", e),
                         };
                     }
+                    CommandExecInfo::Output(ExecutionItem::Expect(expected)) => {
+                        view.reserve_slot(&expected_slot(expected.position.start));
+                    }
                     CommandExecInfo::Output(line) => {
                         view.draw_help_line(w)?;
                         match view.write_on(w, line) {
@@ -99,27 +108,95 @@ pub fn run(
                             Err(e) => error!("Error on output: {}", e),
                         };
                     }
-                    CommandExecInfo::Start => {
-                        info!("execution started");
-                        view.draw_executing();
+                    CommandExecInfo::Start { chunk } => {
+                        info!("execution of {} started", chunk.id);
+                        view.draw_executing(&chunk.id);
+                    }
+                    CommandExecInfo::Skipped { chunk, reason } => {
+                        info!("{} skipped: {}", chunk.id, reason);
+                        view.write_command_output(w, format!("skipped, {reason}"))?;
                     }
-                    CommandExecInfo::End { status } => {
+                    CommandExecInfo::End { chunk, status, .. } => {
                         info!("execution finished with status: {:?}", status);
+                        view.chunk_ends(&chunk.id);
+                        view.execution_stops();
+                    }
+                    CommandExecInfo::Built {
+                        chunk,
+                        status,
+                        passed,
+                        ..
+                    } => {
+                        info!("build of {} finished with status: {:?}", chunk.id, status);
+                        let line = match status {
+                            // the output of the program comes next
+                            Some(_) if passed => "built".to_string(),
+                            Some(status) => format!("build failed, {status}"),
+                            None => "build failed, no exit status".to_string(),
+                        };
+                        view.write_chunk_output(w, &chunk.id, line)?;
+                        if !passed {
+                            view.chunk_ends(&chunk.id);
+                            view.execution_stops();
+                        }
+                    }
+                    CommandExecInfo::Captured { chunk, name, .. } => {
+                        info!("{} captured {}", chunk.id, name);
+                    }
+                    CommandExecInfo::Retry {
+                        chunk,
+                        attempt,
+                        reason,
+                        ..
+                    } => {
+                        info!("attempt {} of {} failed", attempt, chunk.id);
+                        let line = format!("attempt {attempt} failed, {reason}, retrying");
+                        view.write_chunk_output(w, &chunk.id, line)?;
+                    }
+                    CommandExecInfo::TimedOut { chunk, timeout, .. } => {
+                        info!("{} timed out", chunk.id);
+                        view.write_chunk_output(w, &chunk.id, timed_out(&timeout))?;
+                        view.chunk_ends(&chunk.id);
                         view.execution_stops();
                     }
-                    CommandExecInfo::Error(e) => {
-                        warn!("error in computation: {}", e);
-                        match view.write_command_output(w, e) {
+                    CommandExecInfo::Assertion {
+                        chunk,
+                        expected,
+                        diff,
+                        ..
+                    } => {
+                        info!("output of {} checked, diff: {:?}", chunk.id, diff);
+                        let slot = expected_slot(expected.start);
+                        if let Some(diff) = diff {
+                            for line in diff.lines() {
+                                view.write_chunk_output(w, &slot, line.to_string())?;
+                            }
+                        }
+                        view.chunk_ends(&slot);
+                    }
+                    CommandExecInfo::Error { chunk, message } => {
+                        warn!("error in computation: {}", message);
+                        let written = match &chunk {
+                            Some(chunk) => view.write_chunk_output(w, &chunk.id, message),
+                            None => view.write_command_output(w, message),
+                        };
+                        match written {
                             Ok(_) => debug!("Output written"),
                             Err(e) => error!("Error on output: {}", e),
                         };
+                        if let Some(chunk) = chunk {
+                            view.chunk_ends(&chunk.id);
+                        }
                         view.execution_stops();
-                        break;
                     }
                     CommandExecInfo::Interruption => {
                         debug!("command was interrupted (by us)");
                         break;
                     }
+                    CommandExecInfo::Finished => {
+                        info!("execution plan finished");
+                        view.execution_stops();
+                    }
                 }
                 w.flush()?;
             }
@@ -148,3 +225,8 @@ pub fn run(
     executor.die()?;
     Ok(action)
 }
+
+/// key of the place in the view of the result of an expected output block
+fn expected_slot(start: usize) -> String {
+    format!("output block at line {}", start + 1)
+}
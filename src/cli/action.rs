@@ -16,6 +16,7 @@ pub enum Action {
     Internal(Internal),
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum ParseActionError {
     UnknownAction(String),
@@ -1,46 +1,190 @@
 use anyhow::{bail, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
 /// markdown-gem runs your MD files as code chunks.
 ///
 /// Documentation at <https://github.com/murabi-io/murabi>
 #[derive(Debug, Parser, Default)]
-#[clap(author, version, about)]
+#[clap(author, version, about, args_conflicts_with_subcommands = true)]
 pub struct Args {
     /// verbose mode
-    #[clap(long = "vvv")]
+    #[clap(long = "vvv", global = true)]
     pub verbose: bool,
 
     /// path to the log file
-    #[clap(short = 'l', long = "log")]
+    #[clap(short = 'l', long = "log", global = true)]
     pub log_file: Option<String>,
 
     /// path to MD file or directory with MD
-    #[clap(short = 'p', long = "path")]
+    #[clap(short = 'p', long = "path", global = true)]
     pub path: Option<String>,
 
     /// if specified, markdown-gem won't delete the build file of the Code chunks
-    #[clap(short = 'k', long = "keep")]
+    #[clap(short = 'k', long = "keep", global = true)]
     pub keep_builds: bool,
 
+    /// where the code chunks run, and where their relative `cwd` starts from:
+    /// the `workspace` root or the `markdown` file directory, default `workspace`
+    #[clap(long = "workdir", value_name = "POLICY", value_parser = ["workspace", "markdown"], global = true)]
+    pub workdir: Option<String>,
+
+    /// directory of the build files of the Code chunks, relative to the workspace, default `.mrbuild`
+    #[clap(long = "build-dir", value_name = "PATH", global = true)]
+    pub build_dir: Option<String>,
+
+    /// run without the terminal UI, printing the markdown and the code chunks
+    /// output to stdout, the exit code is non-zero if any code chunk fails
+    #[clap(long = "ci", visible_alias = "no-tui", global = true)]
+    pub ci: bool,
+
+    /// path of the JUnit XML report to write, with a testcase per code chunk
+    #[clap(long = "junit", value_name = "PATH", global = true)]
+    pub junit: Option<String>,
+
+    /// path of the self-contained HTML report to write, with the rendered
+    /// markdown and the output of the code chunks
+    #[clap(long = "html", value_name = "PATH", global = true)]
+    pub html: Option<String>,
+
+    /// run without the terminal UI, writing the execution to stdout in the given format
+    #[clap(long = "format", value_enum, global = true)]
+    pub format: Option<Format>,
+
+    /// run all the MD files found in the directory tree, honouring `.gitignore`
+    #[clap(short = 'r', long = "recursive", global = true)]
+    pub recursive: bool,
+
+    /// glob of the files to run with `--recursive`, default `*.md`
+    #[clap(long = "include", value_name = "GLOB", global = true)]
+    pub include: Vec<String>,
+
+    /// glob of the files to skip with `--recursive`
+    #[clap(long = "exclude", value_name = "GLOB", global = true)]
+    pub exclude: Vec<String>,
+
+    /// maximum number of code chunks marked `parallel` running at the same time, default 1
+    #[clap(short = 'j', long = "jobs", value_name = "N", global = true)]
+    pub jobs: Option<usize>,
+
+    /// default timeout of the code chunks, e.g. `30s` or `5m`
+    #[clap(long = "timeout", value_name = "DURATION", value_parser = humantime::parse_duration, global = true)]
+    pub timeout: Option<std::time::Duration>,
+
+    /// run only the code chunk with this id, and the code chunks it depends on
+    #[clap(long = "chunk", value_name = "ID", global = true)]
+    pub chunks: Vec<String>,
+
+    /// run only the code chunks whose tags match the expression,
+    /// e.g. `--tags "network and not slow"`
+    #[clap(long = "tags", value_name = "EXPR", global = true)]
+    pub tags: Option<String>,
+
+    /// skip the code chunks whose tags match the expression,
+    /// e.g. `--skip-tags destructive`
+    #[clap(long = "skip-tags", value_name = "EXPR", global = true)]
+    pub skip_tags: Option<String>,
+
+    /// value of a parameter of the markdown file, replacing its `{{NAME}}`
+    /// in the code chunks, e.g. `--param version=1.2.3`
+    #[clap(long = "param", value_name = "NAME=VALUE", value_parser = parse_param, global = true)]
+    pub params: Vec<(String, String)>,
+
+    /// stop at the first failing code chunk or output block, skipping the rest
+    #[clap(long = "fail-fast", conflicts_with = "keep_going", global = true)]
+    pub fail_fast: bool,
+
+    /// run all the code chunks whatever fails, the default
+    #[clap(long = "keep-going", global = true)]
+    pub keep_going: bool,
+
+    /// rewrite the expected output blocks of the markdown file with the
+    /// output captured during the run
+    #[clap(long = "bless", global = true)]
+    pub bless: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
     #[clap()]
     /// either a path to the folder, file name, or both
     pub args: Vec<String>,
 }
 
+/// the markdown-gem commands, `run` being the default one
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// run the code chunks of the markdown file
+    Run {
+        #[clap()]
+        /// either a path to the folder, file name, or both
+        args: Vec<String>,
+    },
+    /// run the code chunks of the markdown file and write a new markdown
+    /// file with the output of each code chunk embedded after it
+    Knit {
+        /// path to the MD file to run
+        input: String,
+
+        /// path of the MD file to write
+        #[clap(short = 'o', long = "output")]
+        output: String,
+
+        /// add the exit status of each code chunk after its output
+        #[clap(long = "status")]
+        with_status: bool,
+    },
+}
+
+/// a `NAME=VALUE` parameter
+fn parse_param(param: &str) -> Result<(String, String), String> {
+    match param.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected `NAME=VALUE`, got `{param}`")),
+    }
+}
+
+/// format of the output when running without the terminal UI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Format {
+    /// the markdown and the output of the code chunks as plain text
+    #[default]
+    Plain,
+    /// one JSON object per line for every event of the execution
+    Ndjson,
+}
+
 impl Args {
     /// positional arguments in markdown-gem command are a convenience
     /// allowing to skip writing `-p`.
     /// To be used, it must be copied to `path` value.
     pub fn fix(&mut self) -> Result<()> {
+        match self.command.as_mut() {
+            Some(Command::Run { args }) => self.args.append(args),
+            Some(Command::Knit { input, .. }) => {
+                if self.path.is_some() {
+                    bail!("Too many arguments");
+                }
+                if self.recursive {
+                    bail!("knit runs a single MD file, it can't be recursive");
+                }
+                self.path = Some(input.clone());
+                // knitting doesn't need the terminal UI
+                self.format.get_or_insert(Format::Plain);
+            }
+            None => {}
+        }
         let mut args = self.args.drain(..);
         let path = match (args.next(), self.path.is_none()) {
             (Some(a), true) => Some(a),
             (Some(_), false) => bail!("Too many arguments"),
-            _ => None,
+            _ => self.path.take(),
         };
 
         self.path = path;
+        // running a directory tree is meant for CI and reports, not for the terminal UI
+        if (self.ci || self.recursive) && self.format.is_none() {
+            self.format = Some(Format::Plain);
+        }
         Ok(())
     }
 }
@@ -1,22 +1,33 @@
 use std::io::{stdout, BufWriter};
 
-use anyhow::bail;
+use anyhow::{bail, Context};
 use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 
-use crate::app;
+use crate::{app, headless};
 use crossterm::{
     self, cursor,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen},
     QueueableCommand,
 };
-use std::{fs, io::Write};
-use termimad::{minimad, EventSource, EventSourceOptions};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use termimad::{EventSource, EventSourceOptions};
 
-use crate::cli::action::Action;
-use crate::cli::args::Args;
-use crate::cli::keybindings::KeyBindings;
+use crate::cli::args::{Args, Command, Format};
+use crate::cli::config::Config;
+use crate::executor::dependency_graph::DependencyGraph;
 use crate::executor::execution_plan::ExecutionPlan;
+use crate::executor::failure::write_failures;
 use crate::executor::job_location::JobLocation;
+use crate::executor::params;
+use crate::executor::tags::TagFilter;
+use crate::executor::ExecutorOptions;
+use crate::export::{bless, html, knit};
+use crate::report::{junit, RunReport};
 use crate::view::View;
 
 /// the type used by all GUI writing functions
@@ -34,20 +45,194 @@ pub fn writer() -> W {
     BufWriter::new(stdout())
 }
 
-pub fn run(args: &Args) -> anyhow::Result<Option<Action>> {
-    let location = JobLocation::new(args)?;
-    info!("mission location: {:#?}", &location);
+/// run markdown-gem and return the exit code of the process
+pub fn run(args: &Args) -> anyhow::Result<exitcode::ExitCode> {
+    let locations = if args.recursive {
+        JobLocation::find_all(args)?
+    } else {
+        vec![JobLocation::new(args)?]
+    };
+    info!("mission locations: {:#?}", &locations);
 
-    if location.path_to_md.is_none() {
+    if locations.iter().all(|l| l.path_to_md.is_none()) {
         bail!("markdown file was not found");
     }
-    let keybindings = KeyBindings::default();
-    let mut view = View::new(&keybindings);
+    let config = Config::load(&locations[0].workspace_root)?;
+
+    // the parameters of all the files are checked before anything runs
+    let mut files = vec![];
+    for location in locations {
+        let md_path = match location.path_to_md.clone() {
+            Some(md_path) => md_path,
+            None => continue,
+        };
+        let file_content = fs::read_to_string(&md_path)?;
+        let declared = params::front_matter_params(&file_content)
+            .with_context(|| format!("invalid front matter in {}", md_path.display()))?;
+        let params = params::resolve(&declared, &config.params, &args.params)
+            .with_context(|| format!("can't run {}", md_path.display()))?;
+        files.push((location, md_path, file_content, params));
+    }
+
+    let mut report = RunReport::default();
+    let mut md_paths = vec![];
+    let mut files_failed = vec![];
+
+    for (location, md_path, file_content, params) in files {
+        let errors_before = report.errors.len();
+        let result = run_file(
+            args,
+            &config,
+            location,
+            &md_path,
+            &file_content,
+            &params,
+            &mut report,
+        );
+        match result {
+            // the other files of a recursive run still run
+            Err(e) if args.recursive => {
+                report.errors.push((md_path.clone(), format!("{e:#}")));
+            }
+            result => result?,
+        }
+        files_failed.push(report.errors.len() > errors_before);
+        md_paths.push(md_path);
+        if args.fail_fast && report.failures() > 0 {
+            info!("stopping after the first failure");
+            break;
+        }
+    }
+
+    if let Some(Command::Knit {
+        output,
+        with_status,
+        ..
+    }) = &args.command
+    {
+        knit::write_file(&md_paths[0], Path::new(output), &report, *with_status)?;
+        info!("knitted markdown written to {}", output);
+    }
+    if let Some(path) = &args.html {
+        html::write_file(&md_paths, Path::new(path), &report)?;
+        info!("HTML report written to {}", path);
+    }
+    write_reports(args, &report)?;
+
+    if args.recursive && args.format == Some(Format::Plain) {
+        write_summary(&mut writer(), &md_paths, &files_failed, &report)?;
+    }
+    // the events already tell the failures in ndjson
+    if args.format != Some(Format::Ndjson) {
+        write_failures(&mut writer(), &report)?;
+    }
+
+    let failed = report.failures();
+    if failed > 0 {
+        info!("{} failure(s)", failed);
+        return Ok(exitcode::SOFTWARE);
+    }
+    Ok(exitcode::OK)
+}
+
+/// run the code chunks of a markdown file, given with the values
+/// of its parameters, then bless it if asked
+fn run_file(
+    args: &Args,
+    config: &Config,
+    location: JobLocation,
+    md_path: &Path,
+    file_content: &str,
+    params: &BTreeMap<String, String>,
+    report: &mut RunReport,
+) -> anyhow::Result<()> {
+    report.start_file(md_path);
+    // the lines aren't cleaned, so that the positions of the code chunks
+    // are the line numbers in the file
+    let tags = TagFilter::new(args.tags.as_deref(), args.skip_tags.as_deref())?;
+    let mut execution_plan =
+        ExecutionPlan::with_defaults(file_content, &config.default_attributes(), &tags);
+    execution_plan.substitute_params(params);
+    report.hide(md_path, execution_plan.hidden());
+    let graph = DependencyGraph::new(&execution_plan)
+        .with_context(|| format!("invalid dependencies in {}", md_path.display()))?;
+    if !args.chunks.is_empty() {
+        // in a recursive run, the chunks may be in any of the files
+        if let Some(id) = args.chunks.iter().find(|id| !graph.contains(id)) {
+            if !args.recursive {
+                bail!("there's no code chunk `{}` in {}", id, md_path.display());
+            }
+        }
+        execution_plan.select(&graph.with_dependencies(&args.chunks));
+    }
+
+    if let Some(format) = args.format {
+        let mut w = writer();
+        headless::run(
+            &mut w,
+            location,
+            execution_plan,
+            ExecutorOptions::new(args, config),
+            format,
+            report,
+        )?;
+    } else {
+        run_tui(args, config, location, execution_plan, report)?;
+    }
 
-    let md_path = location.path_to_md.as_ref().unwrap();
-    let file_content = fs::read_to_string(md_path)?;
-    let text = minimad::clean::lines(&file_content);
-    let execution_plan = ExecutionPlan::from_md_lines(text.into_iter());
+    if args.bless {
+        let blessed = bless::bless_file(md_path, report)?;
+        info!("{} output block(s) updated in {:?}", blessed, md_path);
+    }
+    Ok(())
+}
+
+/// write the pass/fail line of every markdown file of a recursive run
+fn write_summary<W: Write>(
+    w: &mut W,
+    md_paths: &[PathBuf],
+    files_failed: &[bool],
+    report: &RunReport,
+) -> anyhow::Result<()> {
+    writeln!(w)?;
+    let mut failed = 0;
+    for (md_path, &errors) in md_paths.iter().zip(files_failed) {
+        let summary = report.summary(md_path);
+        let verdict = if errors || summary.failed > 0 {
+            failed += 1;
+            "FAIL"
+        } else {
+            "PASS"
+        };
+        writeln!(
+            w,
+            "{verdict} {}: {} passed, {} failed, {} skipped",
+            md_path.display(),
+            summary.passed,
+            summary.failed,
+            summary.skipped,
+        )?;
+    }
+    writeln!(
+        w,
+        "{} file(s), {} passed, {} failed",
+        md_paths.len(),
+        md_paths.len() - failed,
+        failed
+    )?;
+    w.flush()?;
+    Ok(())
+}
+
+/// run the execution plan in the terminal UI
+fn run_tui(
+    args: &Args,
+    config: &Config,
+    location: JobLocation,
+    execution_plan: ExecutionPlan,
+    report: &mut RunReport,
+) -> anyhow::Result<()> {
+    let mut view = View::new(&config.keybindings, &config.skin);
 
     let mut w = writer();
 
@@ -67,7 +252,8 @@ pub fn run(args: &Args) -> anyhow::Result<Option<Action>> {
         location,
         execution_plan,
         &event_source,
-        args.keep_builds,
+        ExecutorOptions::new(args, config),
+        report,
     );
 
     w.flush()?;
@@ -75,5 +261,15 @@ pub fn run(args: &Args) -> anyhow::Result<Option<Action>> {
     w.queue(DisableMouseCapture)?;
 
     w.queue(LeaveAlternateScreen)?;
-    result
+    w.flush()?;
+    result.map(|_| ())
+}
+
+/// write the report files requested in the arguments
+fn write_reports(args: &Args, report: &RunReport) -> anyhow::Result<()> {
+    if let Some(path) = &args.junit {
+        junit::write_file(report, Path::new(path))?;
+        info!("JUnit report written to {}", path);
+    }
+    Ok(())
 }
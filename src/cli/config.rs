@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::cli::keybindings::KeyBindings;
+use crate::executor::languages::Languages;
+use crate::fenced_attributes::{Attributes, WorkDir};
+
+/// name of the configuration file, looked for in the user config
+/// directory and in the workspace root
+pub static CONFIG_FILE_NAME: &str = "gem.toml";
+
+/// markdown-gem configuration, read from the `gem.toml` of the user
+/// config directory then from the one of the workspace root, the latter
+/// taking precedence. The command line arguments take precedence over both.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// key bindings of the terminal UI, added to the default ones
+    pub keybindings: KeyBindings,
+    /// commands of the code chunk languages, added to the built-in ones
+    pub languages: Languages,
+    /// default attributes of the code chunks
+    pub defaults: toml::value::Table,
+    /// default values of the parameters of the markdown files
+    pub params: BTreeMap<String, String>,
+    /// directory of the build files, relative to the workspace root
+    pub build_dir: Option<PathBuf>,
+    /// where the code chunks run, and where their relative `cwd` starts from
+    pub workdir: Option<WorkDir>,
+    /// maximum number of `parallel` code chunks running at the same time
+    pub jobs: Option<usize>,
+    /// timeout of the code chunks without a `timeout` attribute
+    pub timeout: Option<Duration>,
+    /// keep the build files once the code chunks are executed
+    pub keep_builds: bool,
+    /// stop at the first failure
+    pub fail_fast: bool,
+    /// colors of the terminal UI
+    pub skin: Skin,
+}
+
+/// colors of the terminal UI, as ANSI values from 0 to 255
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Skin {
+    pub headers: Option<u8>,
+    pub bold: Option<u8>,
+    pub italic: Option<u8>,
+    pub code: Option<u8>,
+    pub scrollbar: Option<u8>,
+    /// background of the status line
+    pub status: Option<u8>,
+}
+
+/// the content of one configuration file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    keybindings: Option<KeyBindings>,
+    languages: Option<Languages>,
+    #[serde(default, deserialize_with = "deserialize_defaults")]
+    defaults: Option<toml::value::Table>,
+    #[serde(default, deserialize_with = "deserialize_params")]
+    params: Option<BTreeMap<String, String>>,
+    build_dir: Option<PathBuf>,
+    workdir: Option<WorkDir>,
+    jobs: Option<usize>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    timeout: Option<Duration>,
+    keep_builds: Option<bool>,
+    fail_fast: Option<bool>,
+    skin: Option<Skin>,
+}
+
+impl Config {
+    /// load the configuration files of the user and of the workspace
+    pub fn load(workspace_root: &Path) -> Result<Self> {
+        let mut config = Self::default();
+        let paths = user_config_dir()
+            .into_iter()
+            .chain(Some(workspace_root.to_path_buf()))
+            .map(|dir| dir.join(CONFIG_FILE_NAME));
+        for path in paths {
+            if path.is_file() {
+                info!("loading configuration {:?}", path);
+                config.apply(read_file(&path)?);
+            }
+        }
+        Ok(config)
+    }
+
+    /// the default attributes of the code chunks, in HOCON
+    pub fn default_attributes(&self) -> String {
+        if self.defaults.is_empty() {
+            return String::new();
+        }
+        // JSON is valid HOCON
+        serde_json::to_string(&self.defaults).unwrap_or_default()
+    }
+
+    /// update the configuration with a file, its values taking precedence
+    fn apply(&mut self, file: ConfigFile) {
+        if let Some(keybindings) = &file.keybindings {
+            self.keybindings.add_all(keybindings);
+        }
+        if let Some(languages) = &file.languages {
+            self.languages.add_all(languages);
+        }
+        self.defaults.extend(file.defaults.unwrap_or_default());
+        self.params.extend(file.params.unwrap_or_default());
+        self.build_dir = file.build_dir.or(self.build_dir.take());
+        self.workdir = file.workdir.or(self.workdir);
+        self.jobs = file.jobs.or(self.jobs);
+        self.timeout = file.timeout.or(self.timeout);
+        self.keep_builds = file.keep_builds.unwrap_or(self.keep_builds);
+        self.fail_fast = file.fail_fast.unwrap_or(self.fail_fast);
+        if let Some(skin) = file.skin {
+            self.skin = Skin {
+                headers: skin.headers.or(self.skin.headers),
+                bold: skin.bold.or(self.skin.bold),
+                italic: skin.italic.or(self.skin.italic),
+                code: skin.code.or(self.skin.code),
+                scrollbar: skin.scrollbar.or(self.skin.scrollbar),
+                status: skin.status.or(self.skin.status),
+            };
+        }
+    }
+}
+
+/// read a configuration file, its errors telling the file and the line
+fn read_file(path: &Path) -> Result<ConfigFile> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("can't read {}", path.display()))?;
+    // the toml errors end with their line and column
+    parse(&content).map_err(|e| anyhow!("invalid configuration in {}: {}", path.display(), e))
+}
+
+fn parse(content: &str) -> Result<ConfigFile, toml::de::Error> {
+    toml::from_str(content)
+}
+
+/// the directory of the user configuration, e.g. `~/.config/markdown-gem`
+fn user_config_dir() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+    Some(config_dir.join("markdown-gem"))
+}
+
+/// a duration, e.g. `30s` or `5m`
+fn deserialize_duration<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+    let value = String::deserialize(d)?;
+    humantime::parse_duration(&value)
+        .map(Some)
+        .map_err(|e| de::Error::custom(format!("invalid duration `{value}`: {e}")))
+}
+
+/// parameters, their values being strings, numbers or booleans
+fn deserialize_params<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<BTreeMap<String, String>>, D::Error> {
+    let table = toml::value::Table::deserialize(d)?;
+    let mut params = BTreeMap::new();
+    for (name, value) in table {
+        let value = match value {
+            toml::Value::String(value) => value,
+            toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => {
+                value.to_string()
+            }
+            _ => {
+                return Err(de::Error::custom(format!(
+                    "the parameter `{name}` must be a string, a number or a boolean"
+                )))
+            }
+        };
+        params.insert(name, value);
+    }
+    Ok(Some(params))
+}
+
+/// default attributes, which must be valid code chunk attributes
+fn deserialize_defaults<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<toml::value::Table>, D::Error> {
+    let defaults = toml::value::Table::deserialize(d)?;
+    if defaults.contains_key("id") {
+        return Err(de::Error::custom(
+            "the `id` of a code chunk can't have a default",
+        ));
+    }
+    let hocon = serde_json::to_string(&defaults).map_err(de::Error::custom)?;
+    let attributes = Attributes::parse(&hocon)
+        .map_err(|e| de::Error::custom(format!("invalid default attributes: {e}")))?;
+    attributes.timeout().map_err(de::Error::custom)?;
+    attributes.retry_delay().map_err(de::Error::custom)?;
+    Ok(Some(defaults))
+}
+
+#[test]
+fn test_parse_config() {
+    let config = parse(
+        r#"
+        jobs = 4
+        timeout = "1m"
+        build_dir = "target/gem"
+
+        [languages]
+        py = ["python3", "-u"]
+
+        [defaults]
+        allow_warnings = false
+        retries = 1
+
+        [params]
+        version = "1.2.3"
+        build = 42
+
+        [skin]
+        headers = 33
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.jobs, Some(4));
+    assert_eq!(config.timeout, Some(Duration::from_secs(60)));
+    assert_eq!(config.skin.unwrap().headers, Some(33));
+    assert_eq!(config.params.as_ref().unwrap()["build"], "42");
+
+    let mut merged = Config::default();
+    merged.apply(config);
+    merged.apply(parse("jobs = 2\n[defaults]\nretries = 3").unwrap());
+    assert_eq!(merged.jobs, Some(2));
+    assert_eq!(merged.timeout, Some(Duration::from_secs(60)));
+    let defaults = Attributes::parse(&merged.default_attributes()).unwrap();
+    assert!(!defaults.allow_warnings);
+    assert_eq!(defaults.retries, 3);
+    assert_eq!(merged.languages.get("py").map(|c| c.len()), Some(2),);
+}
+
+#[test]
+fn test_config_errors_have_a_line() {
+    for content in [
+        "jobs = 2\ntimeout = \"forever\"",
+        "jobs = 2\nunknown = 1",
+        "jobs = 2\n[defaults]\nretries = \"many\"",
+        "jobs = 2\n[skin]\nbold = 300",
+    ] {
+        let error = parse(content).unwrap_err();
+        assert!(error.line_col().is_some(), "no line for {error}");
+    }
+}
@@ -1,6 +1,8 @@
 pub(crate) mod action;
 pub(crate) mod args;
+#[allow(clippy::module_inception)]
 mod cli;
+pub(crate) mod config;
 pub(crate) mod help_line;
 pub(crate) mod help_page;
 pub(crate) mod internal;
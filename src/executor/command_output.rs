@@ -1,15 +1,23 @@
+use std::path::PathBuf;
 use std::process::ExitStatus;
+use std::time::Duration;
 
+use serde::{Serialize, Serializer};
+
+use crate::executor::executable::{Executable, ExecutablePosition};
 use crate::executor::execution_plan::ExecutionItem;
+use crate::executor::job_location::JobLocation;
+use crate::fenced_attributes::OutputMatch;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CommandStream {
     StdOut,
     StdErr,
 }
 
 /// a line coming either from stdout or from stderr
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CommandOutputLine {
     pub content: String,
     pub origin: CommandStream,
@@ -21,24 +29,214 @@ pub struct CommandOutput {
     pub lines: Vec<CommandOutputLine>,
 }
 
+impl CommandOutput {
+    /// the content of the lines coming from the given stream
+    pub fn content(&self, origin: CommandStream) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter(|l| l.origin == origin)
+            .map(|l| l.content.as_str())
+            .collect()
+    }
+}
+
+/// the code chunk a piece of information is about
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChunkRef {
+    /// id of the code chunk
+    pub id: String,
+    /// the markdown file containing the code chunk
+    pub md_file: PathBuf,
+    /// position of the code chunk in the markdown file
+    pub position: ExecutablePosition,
+    /// whether the id was generated, the code chunk having none
+    #[serde(skip)]
+    pub generated_id: bool,
+}
+
+impl ChunkRef {
+    pub fn new(location: &JobLocation, executable: &Executable) -> Self {
+        let attributes = executable.code_chunk.as_ref().map(|c| &c.attributes);
+        Self {
+            id: attributes.and_then(|a| a.id.clone()).unwrap_or_default(),
+            md_file: location.path_to_md.clone().unwrap_or_default(),
+            position: executable.position.clone(),
+            generated_id: match attributes {
+                Some(a) => a.generated_id,
+                None => true,
+            },
+        }
+    }
+
+    /// the id of the code chunk, or its place in the markdown file,
+    /// stable from a run to the next, when the id was generated
+    pub fn name(&self) -> String {
+        if self.generated_id {
+            format!("{}:{}", self.md_file.display(), self.position.start + 1)
+        } else {
+            self.id.clone()
+        }
+    }
+}
+
 /// a piece of information about the execution of a command
+///
+/// Serialized as a JSON object tagged by `event`, the chunk fields
+/// are flattened into the object.
+#[allow(clippy::large_enum_variant)]
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
 pub enum CommandExecInfo {
     /// Command ended
-    End { status: Option<ExitStatus> },
+    End {
+        #[serde(flatten)]
+        chunk: ChunkRef,
+        #[serde(serialize_with = "serialize_status")]
+        status: Option<ExitStatus>,
+        /// whether the code chunk passed, considering `allow_errors` and `allow_warnings`
+        passed: bool,
+        #[serde(serialize_with = "serialize_duration")]
+        duration: Duration,
+    },
+
+    /// The build phase of a code chunk ended, its command runs
+    /// next if the build passed, otherwise the code chunk ends
+    Built {
+        #[serde(flatten)]
+        chunk: ChunkRef,
+        #[serde(serialize_with = "serialize_status")]
+        status: Option<ExitStatus>,
+        passed: bool,
+        #[serde(serialize_with = "serialize_duration")]
+        duration: Duration,
+    },
+
+    /// A value was captured from the stdout of a code chunk,
+    /// the next code chunks get it as an env variable
+    Captured {
+        #[serde(flatten)]
+        chunk: ChunkRef,
+        name: String,
+        value: String,
+    },
 
     /// Command started
-    Start,
+    Start {
+        #[serde(flatten)]
+        chunk: ChunkRef,
+    },
+
+    /// The code chunk was filtered out and won't be executed
+    Skipped {
+        #[serde(flatten)]
+        chunk: ChunkRef,
+        reason: String,
+    },
+
+    /// An attempt of the command failed, it's run again after the delay
+    Retry {
+        #[serde(flatten)]
+        chunk: ChunkRef,
+        /// number of the failed attempt, starting at 1
+        attempt: usize,
+        /// how the attempt failed
+        reason: String,
+        #[serde(serialize_with = "serialize_duration")]
+        delay: Duration,
+    },
+
+    /// The command ran longer than its timeout, it was killed with its descendants
+    TimedOut {
+        #[serde(flatten)]
+        chunk: ChunkRef,
+        #[serde(serialize_with = "serialize_duration")]
+        timeout: Duration,
+        /// how long the code chunk ran, its build and its attempts included
+        #[serde(serialize_with = "serialize_duration")]
+        duration: Duration,
+    },
 
     /// Murabi killed the command
     #[allow(dead_code)]
     Interruption,
 
     /// Execution failed
-    Error(String),
+    Error {
+        #[serde(flatten)]
+        chunk: Option<ChunkRef>,
+        message: String,
+    },
 
     /// Here's a line of output (coming from stderr or stdout)
-    Line(CommandOutputLine),
+    Line {
+        #[serde(flatten)]
+        chunk: ChunkRef,
+        #[serde(flatten)]
+        line: CommandOutputLine,
+    },
+
+    /// The output of a code chunk was compared with an expected output block
+    Assertion {
+        #[serde(flatten)]
+        chunk: ChunkRef,
+        /// position of the expected output block
+        expected: ExecutablePosition,
+        matching: OutputMatch,
+        passed: bool,
+        /// unified diff from the expected to the actual output, or the
+        /// reason the comparison failed
+        diff: Option<String>,
+    },
 
     /// Output an MD line coming from execution plan directly
+    #[serde(skip)]
     Output(ExecutionItem),
+
+    /// All the items of the execution plan were processed
+    Finished,
+}
+
+impl CommandExecInfo {
+    /// the code chunk the information is about, if any
+    pub fn chunk(&self) -> Option<&ChunkRef> {
+        match self {
+            Self::End { chunk, .. }
+            | Self::Built { chunk, .. }
+            | Self::Captured { chunk, .. }
+            | Self::Start { chunk }
+            | Self::Skipped { chunk, .. }
+            | Self::Retry { chunk, .. }
+            | Self::TimedOut { chunk, .. }
+            | Self::Line { chunk, .. }
+            | Self::Assertion { chunk, .. } => Some(chunk),
+            Self::Error { chunk, .. } => chunk.as_ref(),
+            Self::Interruption | Self::Output(_) | Self::Finished => None,
+        }
+    }
+}
+
+/// serialize an exit status as `{"success": bool, "code": int or null}`
+fn serialize_status<S: Serializer>(
+    status: &Option<ExitStatus>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Status {
+        success: bool,
+        code: Option<i32>,
+    }
+    status
+        .map(|s| Status {
+            success: s.success(),
+            code: s.code(),
+        })
+        .serialize(serializer)
+}
+
+/// serialize a duration as a number of seconds
+fn serialize_duration<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
 }
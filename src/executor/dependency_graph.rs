@@ -0,0 +1,183 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Result};
+
+use crate::executor::execution_plan::{ExecutionItem, ExecutionPlan};
+
+/// The dependencies between the code chunks of an execution plan,
+/// declared with the `depends_on` attribute
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    /// the ids of the dependencies of each code chunk, in document order
+    dependencies: Vec<(String, Vec<String>)>,
+}
+
+impl DependencyGraph {
+    /// build the graph of the code chunks of the plan, failing when two
+    /// code chunks which run have the same id, when a dependency doesn't
+    /// exist or when the dependencies form a cycle. The skipped code chunks
+    /// can share the id of another one, e.g. its variant for another `sys`
+    pub fn new(plan: &ExecutionPlan) -> Result<Self> {
+        let mut lines: HashMap<&str, usize> = HashMap::new();
+        let mut graph = Self::default();
+        for item in plan.plan.iter().rev() {
+            let (e, runs) = match item {
+                ExecutionItem::Execute(e) => (e, true),
+                ExecutionItem::Skip(e, _) => (e, false),
+                _ => continue,
+            };
+            let id = match e.id() {
+                Some(id) => id,
+                None => continue,
+            };
+            if runs {
+                if let Some(line) = lines.insert(id, e.position.start + 1) {
+                    bail!(
+                        "the code chunks at lines {line} and {} have the same id `{id}`",
+                        e.position.start + 1
+                    );
+                }
+                // the dependencies of the code chunk which runs prevail
+                graph.dependencies.retain(|(i, _)| i != id);
+            } else if graph.contains(id) {
+                continue;
+            }
+            graph
+                .dependencies
+                .push((id.to_string(), e.dependencies().to_vec()));
+        }
+        graph.check()?;
+        Ok(graph)
+    }
+
+    /// whether a code chunk has the given id
+    pub fn contains(&self, id: &str) -> bool {
+        self.dependencies.iter().any(|(i, _)| i == id)
+    }
+
+    /// the ids of the direct dependencies of a code chunk
+    pub fn dependencies_of(&self, id: &str) -> &[String] {
+        self.dependencies
+            .iter()
+            .find(|(i, _)| i == id)
+            .map(|(_, deps)| deps.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// the given code chunks with all their transitive dependencies
+    pub fn with_dependencies<'g>(&'g self, ids: &'g [String]) -> HashSet<&'g str> {
+        let mut selected = HashSet::new();
+        let mut stack: Vec<&str> = ids.iter().map(String::as_str).collect();
+        while let Some(id) = stack.pop() {
+            if selected.insert(id) {
+                stack.extend(self.dependencies_of(id).iter().map(String::as_str));
+            }
+        }
+        selected
+    }
+
+    fn check(&self) -> Result<()> {
+        for (id, deps) in &self.dependencies {
+            if let Some(dep) = deps.iter().find(|d| !self.contains(d)) {
+                bail!("code chunk `{id}` depends on `{dep}`, which doesn't exist");
+            }
+        }
+        let mut visited = HashSet::new();
+        for (id, _) in &self.dependencies {
+            self.visit(id, &mut vec![], &mut visited)?;
+        }
+        Ok(())
+    }
+
+    /// depth first visit of the dependencies, `path` holding the
+    /// code chunks being visited to report a cycle
+    fn visit<'g>(
+        &'g self,
+        id: &'g str,
+        path: &mut Vec<&'g str>,
+        visited: &mut HashSet<&'g str>,
+    ) -> Result<()> {
+        if let Some(start) = path.iter().position(|p| *p == id) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(id);
+            bail!(
+                "dependency cycle between code chunks: {}",
+                cycle.join(" -> ")
+            );
+        }
+        if !visited.insert(id) {
+            return Ok(());
+        }
+        path.push(id);
+        for dep in self.dependencies_of(id) {
+            self.visit(dep, path, visited)?;
+        }
+        path.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::executor::dependency_graph::DependencyGraph;
+    use crate::executor::execution_plan::ExecutionPlan;
+
+    #[test]
+    fn transitive_dependencies() {
+        let plan = ExecutionPlan::from(
+            "```sh {id=a}\n```\n```sh {id=b, depends_on=[a]}\n```\n```sh {id=c, depends_on=[b]}\n```\n```sh {id=d}\n```",
+        );
+        let graph = DependencyGraph::new(&plan).unwrap();
+        let ids = vec!["c".to_string()];
+        let mut selected: Vec<&str> = graph.with_dependencies(&ids).into_iter().collect();
+        selected.sort_unstable();
+        assert_eq!(selected, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn unknown_dependency() {
+        let plan = ExecutionPlan::from("```sh {id=a, depends_on=[z]}\n```");
+        assert_eq!(
+            DependencyGraph::new(&plan).unwrap_err().to_string(),
+            "code chunk `a` depends on `z`, which doesn't exist"
+        );
+    }
+
+    #[test]
+    fn duplicate_id() {
+        let plan = ExecutionPlan::from("```sh {id=a}\n```\ntext\n```sh {id=a}\n```");
+        assert_eq!(
+            DependencyGraph::new(&plan).unwrap_err().to_string(),
+            "the code chunks at lines 1 and 4 have the same id `a`"
+        );
+    }
+
+    #[test]
+    fn skipped_variants_share_their_id() {
+        let plan = ExecutionPlan::from(
+            "```sh {id=install, sys=[no-such-os], depends_on=[z]}\n```\n```sh {id=install}\n```\n```sh {id=install, eval=false}\n```\n```sh {id=test, depends_on=[install]}\n```",
+        );
+        let graph = DependencyGraph::new(&plan).unwrap();
+        assert!(graph.contains("install"));
+        assert!(graph.dependencies_of("install").is_empty());
+
+        let plan = ExecutionPlan::from(
+            "```sh {id=install, sys=[no-such-os]}\n```\n```sh {id=install}\n```\n```sh {id=install}\n```",
+        );
+        assert_eq!(
+            DependencyGraph::new(&plan).unwrap_err().to_string(),
+            "the code chunks at lines 3 and 5 have the same id `install`"
+        );
+    }
+
+    #[test]
+    fn dependency_cycle() {
+        let plan = ExecutionPlan::from(
+            "```sh {id=a, depends_on=[c]}\n```\n```sh {id=b, depends_on=[a]}\n```\n```sh {id=c, depends_on=[b]}\n```",
+        );
+        assert_eq!(
+            DependencyGraph::new(&plan).unwrap_err().to_string(),
+            "dependency cycle between code chunks: a -> c -> b -> a"
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Context, Result};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::{fs, path::Path};
+
+lazy_static! {
+    /// a `${VAR}` reference in a value
+    static ref VARIABLE: Regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+}
+
+/// replace the `${VAR}` references of a value, the unknown variables being empty
+pub fn expand<F>(value: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    VARIABLE
+        .replace_all(value, |captures: &Captures| {
+            lookup(&captures[1]).unwrap_or_default()
+        })
+        .into_owned()
+}
+
+/// replace the `${VAR}` references to the known variables, keeping the other ones
+pub fn substitute<F>(value: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    VARIABLE
+        .replace_all(value, |captures: &Captures| {
+            lookup(&captures[1]).unwrap_or_else(|| captures[0].to_string())
+        })
+        .into_owned()
+}
+
+/// whether a name can be referred to with `${VAR}`
+pub fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// read the variables of a dotenv file: `KEY=value` lines, with optional
+/// quotes around the value and an optional `export` before the key
+pub fn read_env_file(path: &Path) -> Result<Vec<(String, String)>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("can't read the env file {}", path.display()))?;
+    let mut variables = vec![];
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{}:{}: expected `KEY=value`", path.display(), idx + 1))?;
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|q| value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q)))
+            .unwrap_or(value);
+        variables.push((key.trim().to_string(), value.to_string()));
+    }
+    Ok(variables)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::{expand, is_variable_name, read_env_file, substitute};
+
+    #[test]
+    fn expand_variables() {
+        let lookup = |name: &str| (name == "HOME").then(|| "/home/gem".to_string());
+        assert_eq!(expand("${HOME}/.cargo/bin", lookup), "/home/gem/.cargo/bin");
+        assert_eq!(expand("${MISSING}:$HOME", lookup), ":$HOME");
+    }
+
+    #[test]
+    fn substitute_known_variables() {
+        let lookup = |name: &str| (name == "TOKEN").then(|| "a1b2".to_string());
+        assert_eq!(
+            substitute("curl -H \"$AUTH ${TOKEN}\" ${HOME}", lookup),
+            "curl -H \"$AUTH a1b2\" ${HOME}"
+        );
+        assert!(is_variable_name("RESOURCE_ID"));
+        assert!(!is_variable_name("2FA"));
+        assert!(!is_variable_name("A-B"));
+    }
+
+    #[test]
+    fn read_dotenv_file() {
+        let path = env::temp_dir().join("markdown-gem-test.env");
+        fs::write(
+            &path,
+            "# registry\nexport REGISTRY=\"http://localhost:4873\"\n\nRUST_LOG = debug\nNAME='a b'\n",
+        )
+        .unwrap();
+        let variables = read_env_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            variables,
+            vec![
+                ("REGISTRY".to_string(), "http://localhost:4873".to_string()),
+                ("RUST_LOG".to_string(), "debug".to_string()),
+                ("NAME".to_string(), "a b".to_string()),
+            ]
+        );
+    }
+}
@@ -1,9 +1,11 @@
 use std::fmt::{Debug, Formatter};
 
+use serde::Serialize;
+
 use crate::fenced_attributes::CodeChunk;
 
 /// Executable position with start and end line numbers
-#[derive(Debug, Default, PartialEq, Eq, Clone, Hash)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Hash, Serialize)]
 pub struct ExecutablePosition {
     pub start: usize,
     pub end: usize,
@@ -54,4 +56,24 @@ impl Executable {
             code,
         }
     }
+
+    /// the id of the code chunk
+    pub fn id(&self) -> Option<&str> {
+        self.code_chunk.as_ref()?.attributes.id.as_deref()
+    }
+
+    /// whether the code chunk may run alongside other `parallel` code chunks
+    pub fn is_parallel(&self) -> bool {
+        self.code_chunk
+            .as_ref()
+            .is_some_and(|c| c.attributes.parallel)
+    }
+
+    /// the ids of the code chunks this one depends on
+    pub fn dependencies(&self) -> &[String] {
+        self.code_chunk
+            .as_ref()
+            .and_then(|c| c.attributes.depends_on.as_deref())
+            .unwrap_or_default()
+    }
 }
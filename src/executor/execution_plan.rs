@@ -1,15 +1,17 @@
 use lazy_static::lazy_static;
-use std::env;
+use std::{
+    collections::{BTreeMap, HashSet},
+    env, mem,
+};
 
 use crate::executor::executable::{Executable, ExecutablePosition};
+use crate::executor::params::substitute;
+use crate::executor::tags::TagFilter;
 use crate::fenced_attributes::code_chunk::CodeChunk;
-use crate::fenced_attributes::Attributes;
+use crate::fenced_attributes::{Attributes, Visibility};
 use crate::minimad::{Line, LineParser};
 
-#[cfg(linux)]
-use sys_info;
-
-#[cfg(linux)]
+#[cfg(target_os = "linux")]
 lazy_static! {
     pub static ref LINUX_ID_LIKE: Option<String> = sys_info::linux_os_release()
         .map(|v| -> Option<String> { v.id_like })
@@ -17,21 +19,29 @@ lazy_static! {
         .flatten();
 }
 
-#[cfg(not(linux))]
+#[cfg(not(target_os = "linux"))]
 lazy_static! {
     pub static ref LINUX_ID_LIKE: Option<String> = None;
 }
 
+/// language of the blocks holding the expected output of a code chunk
+pub const OUTPUT_LANG: &str = "output";
+
 #[cfg(windows)]
-const LINE_ENDING: &'static str = "\r\n";
+const LINE_ENDING: &str = "\r\n";
 #[cfg(not(windows))]
-const LINE_ENDING: &'static str = "\n";
+const LINE_ENDING: &str = "\n";
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ExecutionItem {
     OutputString(String),
     OutputCode(String),
     Execute(Executable),
+    /// a code chunk filtered out, with the reason
+    Skip(Executable, String),
+    /// an `output` block with the expected output of another code chunk
+    Expect(Executable),
 }
 
 /// a plan that is just a collection of items to be executed
@@ -62,6 +72,23 @@ impl<'a> ExecutionPlan {
     /// let text = ExecutionPlan::from_md_lines(md.into_iter());
     /// ```
     pub fn from_md_lines<I>(md_lines: I) -> Self
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        Self::from_md_lines_with_defaults(md_lines, "", &TagFilter::default())
+    }
+
+    /// build the plan of a markdown text, the code chunks attributes
+    /// being on top of the given default attributes, and the code chunks
+    /// rejected by the tag filter being skipped
+    pub fn with_defaults(md: &str, defaults: &str, tags: &TagFilter) -> Self {
+        Self::from_md_lines_with_defaults(md.lines(), defaults, tags)
+    }
+
+    /// parse a text from markdown lines and build the plan, the code
+    /// chunks attributes being on top of the given default attributes,
+    /// and the code chunks rejected by the tag filter being skipped
+    pub fn from_md_lines_with_defaults<I>(md_lines: I, defaults: &str, tags: &TagFilter) -> Self
     where
         I: Iterator<Item = &'a str>,
     {
@@ -80,25 +107,39 @@ impl<'a> ExecutionPlan {
                 parser.line()
             };
             match line {
-                Line::CodeFence(..) if between_fences == true => {
+                Line::CodeFence(..) if between_fences => {
                     let position = current_position.clone().unwrap();
                     let position = position.end(idx);
+                    let is_expected_output = code_chunk.as_ref().is_some_and(|c| {
+                        c.lang.as_deref() == Some(OUTPUT_LANG) && c.attributes.for_id.is_some()
+                    });
                     let executable = Executable::new(position.clone(), code_chunk, code.clone());
 
-                    plan.push(ExecutionItem::Execute(executable));
+                    if is_expected_output {
+                        plan.push(ExecutionItem::Expect(executable));
+                    } else {
+                        plan.push(ExecutionItem::Execute(executable));
+                    }
 
                     between_fences = !between_fences;
                     code_chunk = None;
                 }
-                Line::CodeFence(..) if between_fences == false => {
+                Line::CodeFence(..) if !between_fences => {
                     between_fences = !between_fences;
                     code = String::new();
-                    code_chunk = CodeChunk::parse_fences(String::from(md_line));
+                    code_chunk = CodeChunk::parse_fences(String::from(md_line), defaults);
                     current_position = Some(ExecutablePosition::start(idx));
                 }
                 _ => {
                     if between_fences {
-                        plan.push(ExecutionItem::OutputCode(String::from(md_line)));
+                        // the source of `echo=false` and `include=false` code chunks isn't shown
+                        let echo = match &code_chunk {
+                            Some(c) => c.attributes.visibility() == Visibility::All,
+                            None => true,
+                        };
+                        if echo {
+                            plan.push(ExecutionItem::OutputCode(String::from(md_line)));
+                        }
                         code.push_str(format!("{}{}", md_line, LINE_ENDING).as_str());
                     } else {
                         plan.push(ExecutionItem::OutputString(String::from(md_line)));
@@ -110,17 +151,49 @@ impl<'a> ExecutionPlan {
         plan.reverse();
         plan = plan
             .into_iter()
-            .filter(|item| match item {
-                ExecutionItem::Execute(e) if e.code_chunk.is_some() => {
-                    let attrs = &e.code_chunk.as_ref().unwrap().attributes;
-                    Self::by_sys(attrs) && Self::by_arch(attrs) && Self::by_linux_distro(attrs)
+            .map(|item| match item {
+                // the code chunks with invalid attributes fail instead
+                ExecutionItem::Execute(e)
+                    if e.code_chunk.as_ref().is_some_and(|c| c.error.is_none()) =>
+                {
+                    match Self::skip_reason(&e.code_chunk.as_ref().unwrap().attributes, tags) {
+                        Some(reason) => ExecutionItem::Skip(e, reason),
+                        None => ExecutionItem::Execute(e),
+                    }
                 }
-                _ => true,
+                item => item,
             })
             .collect();
         ExecutionPlan { plan }
     }
 
+    /// the reason to skip a code chunk with the given attributes, if any filter rejects it
+    fn skip_reason(attrs: &Attributes, tags: &TagFilter) -> Option<String> {
+        if !attrs.eval {
+            Some("eval=false".to_string())
+        } else if !Self::by_sys(attrs) {
+            Some(format!(
+                "sys filter {:?} doesn't match {}",
+                attrs.sys.as_ref().unwrap(),
+                env::consts::OS
+            ))
+        } else if !Self::by_arch(attrs) {
+            Some(format!(
+                "arch filter {:?} doesn't match {}",
+                attrs.arch.as_ref().unwrap(),
+                env::consts::ARCH
+            ))
+        } else if !Self::by_linux_distro(attrs) {
+            Some(format!(
+                "linux_distro filter {:?} doesn't match {}",
+                attrs.linux_distro.as_ref().unwrap(),
+                LINUX_ID_LIKE.as_deref().unwrap_or("an unknown distro")
+            ))
+        } else {
+            tags.skip_reason(attrs.tags.as_deref().unwrap_or_default())
+        }
+    }
+
     fn by_sys(attrs: &Attributes) -> bool {
         attrs.sys.is_none()
             || attrs
@@ -149,6 +222,57 @@ impl<'a> ExecutionPlan {
                     .contains(LINUX_ID_LIKE.as_ref().unwrap())
     }
 
+    /// the positions of the code chunks which aren't fully shown, with what's shown of them
+    pub fn hidden(&self) -> Vec<(ExecutablePosition, Visibility)> {
+        self.plan
+            .iter()
+            .rev()
+            .filter_map(|item| match item {
+                ExecutionItem::Execute(e) | ExecutionItem::Skip(e, _) => {
+                    let visibility = e.code_chunk.as_ref()?.attributes.visibility();
+                    (visibility != Visibility::All).then(|| (e.position.clone(), visibility))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// replace the `{{name}}` references to the parameters in the code
+    /// chunks, both in the code to run and in the displayed one
+    pub fn substitute_params(&mut self, params: &BTreeMap<String, String>) {
+        if params.is_empty() {
+            return;
+        }
+        for item in self.plan.iter_mut() {
+            match item {
+                ExecutionItem::OutputCode(line) => *line = substitute(line, params),
+                ExecutionItem::Execute(e)
+                | ExecutionItem::Skip(e, _)
+                | ExecutionItem::Expect(e) => {
+                    e.code = substitute(&e.code, params);
+                }
+                ExecutionItem::OutputString(_) => {}
+            }
+        }
+    }
+
+    /// skip the code chunks whose id isn't in the selection
+    pub fn select(&mut self, selected: &HashSet<&str>) {
+        for item in self.plan.iter_mut() {
+            let unselected = matches!(
+                item,
+                ExecutionItem::Execute(e) if e.id().is_some_and(|id| !selected.contains(id))
+            );
+            if unselected {
+                if let ExecutionItem::Execute(e) =
+                    mem::replace(item, ExecutionItem::OutputString(String::new()))
+                {
+                    *item = ExecutionItem::Skip(e, "not selected".to_string());
+                }
+            }
+        }
+    }
+
     pub fn next(&mut self) -> Option<ExecutionItem> {
         self.plan.pop()
     }
@@ -161,7 +285,8 @@ mod tests {
 
     use crate::executor::executable::{Executable, ExecutablePosition};
     use crate::executor::execution_plan::{ExecutionItem, ExecutionPlan, LINE_ENDING};
-    use crate::fenced_attributes::{Attributes, CodeChunk};
+    use crate::executor::tags::TagFilter;
+    use crate::fenced_attributes::{Attributes, CodeChunk, Visibility};
 
     #[test]
     fn indented_code_between_fences() {
@@ -183,17 +308,11 @@ mod tests {
 
         let expected_attribute_1 = Attributes {
             id: Some("code-1".to_string()),
-            as_file: true,
-            stdout: true,
-            allow_warnings: true,
             ..Attributes::default()
         };
 
         let expected_attribute_2 = Attributes {
             id: Some("code-2".to_string()),
-            as_file: true,
-            stdout: true,
-            allow_warnings: true,
             ..Attributes::default()
         };
 
@@ -206,6 +325,7 @@ mod tests {
                 Some(CodeChunk {
                     lang: Some(String::from("code")),
                     attributes: expected_attribute_1.clone(),
+                    error: None,
                 }),
                 format!("a{}    b{}", LINE_ENDING, LINE_ENDING),
             )),
@@ -217,6 +337,7 @@ mod tests {
                 Some(CodeChunk {
                     lang: Some(String::from("code")),
                     attributes: expected_attribute_2.clone(),
+                    error: None,
                 }),
                 format!("c{}    d{}", LINE_ENDING, LINE_ENDING),
             )),
@@ -229,4 +350,60 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn display_controls() {
+        let md = clean::lines(
+            r#"
+            ```sh {id=shown, eval=false}
+            rm -rf /
+            ```
+            ```sh {id=setup, include=false}
+            mkdir tmp
+            ```
+            ```sh {id=quiet, echo=false}
+            ls tmp
+            ```
+        "#,
+        );
+        let mut plan = ExecutionPlan::from_md_lines(md.into_iter());
+
+        assert_eq!(
+            plan.hidden(),
+            vec![
+                (ExecutablePosition::new(3, 5), Visibility::Nothing),
+                (ExecutablePosition::new(6, 8), Visibility::Output),
+            ]
+        );
+        assert_eq!(
+            plan.next(),
+            Some(ExecutionItem::OutputCode("rm -rf /".to_string()))
+        );
+        assert!(
+            matches!(plan.next(), Some(ExecutionItem::Skip(_, reason)) if reason == "eval=false")
+        );
+        assert!(matches!(plan.next(), Some(ExecutionItem::Execute(e)) if e.code == "mkdir tmp\n"));
+        assert!(matches!(plan.next(), Some(ExecutionItem::Execute(e)) if e.code == "ls tmp\n"));
+        assert_eq!(plan.next(), None);
+    }
+
+    #[test]
+    fn tag_selection() {
+        let md = "```sh {id=a, tags=[network, slow]}\ncurl\n```\n```sh {id=b, tags=[network]}\ncurl\n```\n";
+        let tags = TagFilter::new(Some("network"), Some("slow")).unwrap();
+        let plan = ExecutionPlan::with_defaults(md, "", &tags);
+
+        let skipped: Vec<(usize, &str)> = plan
+            .plan
+            .iter()
+            .filter_map(|item| match item {
+                ExecutionItem::Skip(e, reason) => Some((e.position.start, reason.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![(0, r#"tags ["network", "slow"] match --skip-tags `slow`"#)]
+        );
+    }
 }
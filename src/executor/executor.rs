@@ -1,21 +1,35 @@
 use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
     process::{ExitStatus, Stdio},
+    sync::Arc,
     thread,
+    time::{Duration, Instant},
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
     process::{Child, Command},
-    sync::oneshot,
-    task::JoinHandle,
+    sync::{oneshot, Mutex},
+    task::JoinSet,
 };
 
-use crate::executor::command_output::{CommandExecInfo, CommandOutputLine, CommandStream};
+use crate::cli::args::Args;
+use crate::cli::config::Config;
+use crate::executor::command_output::{
+    ChunkRef, CommandExecInfo, CommandOutputLine, CommandStream,
+};
+use crate::executor::executable::Executable;
 use crate::executor::execution_plan::{ExecutionItem, ExecutionPlan};
-use crate::executor::job::Job;
+use crate::executor::expected_output;
+use crate::executor::job::{Job, BUILD_DIR};
 use crate::executor::job_location::JobLocation;
-use crate::*;
+use crate::executor::languages::Languages;
+use crate::executor::process_group::ProcessGroup;
+use crate::executor::session::{Interpreter, Session};
+use crate::fenced_attributes::WorkDir;
+use crate::report::timed_out;
 
 /// an executor calling a command in a separate
 /// thread when asked to and sending the lines of output in a channel,
@@ -27,12 +41,99 @@ pub struct Executor {
     thread: thread::JoinHandle<()>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Default)]
-pub struct Task {
-    pub backtrace: bool,
+/// how the executor runs the code chunks
+#[derive(Debug, Clone)]
+pub struct ExecutorOptions {
+    /// keep the build files once the code chunks are executed
+    pub keep_files: bool,
+    /// maximum number of `parallel` code chunks running at the same time
+    pub jobs: usize,
+    /// timeout of the code chunks without a `timeout` attribute
+    pub timeout: Option<Duration>,
+    /// skip the remaining code chunks after the first failure
+    pub fail_fast: bool,
+    /// commands of the code chunks without a `cmd` attribute, by language
+    pub languages: Languages,
+    /// directory of the build files, relative to the workspace root
+    pub build_dir: PathBuf,
+    /// where the code chunks without `workdir` attribute run
+    pub workdir: WorkDir,
+}
+
+impl Default for ExecutorOptions {
+    fn default() -> Self {
+        Self::new(&Args::default(), &Config::default())
+    }
+}
+
+impl ExecutorOptions {
+    pub fn new(args: &Args, config: &Config) -> Self {
+        // the arguments take precedence over the configuration
+        Self {
+            keep_files: args.keep_builds || config.keep_builds,
+            jobs: args.jobs.or(config.jobs).unwrap_or(1).max(1),
+            timeout: args.timeout.or(config.timeout),
+            fail_fast: args.fail_fast || !args.keep_going && config.fail_fast,
+            languages: config.languages.clone(),
+            build_dir: args
+                .build_dir
+                .as_ref()
+                .map(PathBuf::from)
+                .or_else(|| config.build_dir.clone())
+                .unwrap_or_else(|| PathBuf::from(BUILD_DIR)),
+            workdir: match args.workdir.as_deref() {
+                Some("markdown") => WorkDir::Markdown,
+                Some(_) => WorkDir::Workspace,
+                None => config.workdir.unwrap_or_default(),
+            },
+        }
+    }
+}
+
+pub(crate) type LineSender = crossbeam::channel::Sender<CommandExecInfo>;
+
+/// what a finished task produced
+pub(crate) struct TaskOutput {
+    pub status: Option<ExitStatus>,
+    pub stdout: Vec<String>,
+    /// number of lines written on stderr
+    pub stderr_lines: usize,
+}
+
+/// how a task ended
+enum TaskEnd {
+    /// the process exited
+    Exited(TaskOutput),
+    /// the process was killed after running longer than the timeout
+    TimedOut(Duration),
+    /// the build phase failed, the command didn't run
+    BuildFailed(Option<ExitStatus>),
 }
 
-type LineSender = crossbeam::channel::Sender<CommandExecInfo>;
+/// a finished task with its job, code chunk and start time
+type FinishedTask = (Job, ChunkRef, Instant, Result<TaskEnd>);
+
+/// the timeout of a code chunk, a single budget for its build, its
+/// attempts and the delays between them
+#[derive(Debug, Clone, Copy)]
+struct Deadline {
+    timeout: Duration,
+    at: Instant,
+}
+
+impl Deadline {
+    fn new(timeout: Option<Duration>, started: Instant) -> Option<Self> {
+        timeout.map(|timeout| Self {
+            timeout,
+            at: started + timeout,
+        })
+    }
+
+    /// the time left before the code chunk is killed
+    fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(Instant::now())
+    }
+}
 
 impl Executor {
     /// launch the commands, send the lines of its stderr/out on the
@@ -40,7 +141,7 @@ impl Executor {
     pub fn new(
         location: JobLocation,
         mut execution_plan: ExecutionPlan,
-        keep_files: bool,
+        options: ExecutorOptions,
     ) -> Result<Self> {
         let (stop_sender, mut stop_receiver) = oneshot::channel();
         let (line_sender, line_receiver) = crossbeam::channel::unbounded();
@@ -49,17 +150,48 @@ impl Executor {
             // start a runtime to manage the executor
             let rt = tokio::runtime::Builder::new_current_thread()
                 .enable_io()
+                .enable_time()
                 .build()
                 .unwrap();
 
             rt.block_on(async move {
-                // Handle to the current task
+                let mut state = RunState::default();
+                let mut running: JoinSet<FinishedTask> = JoinSet::new();
+                // code chunks waiting for their dependencies to run
+                let mut waiting: Vec<Executable> = vec![];
+                // expected output blocks of the waiting or running code chunks
+                let mut waiting_expects: Vec<Executable> = vec![];
+
+                'plan: loop {
+                    check_ready_outputs(&mut waiting_expects, &waiting, &mut state, &line_sender);
 
-                loop {
-                    let mut current_task: Option<tokio::task::JoinHandle<_>> = None;
-                    let maybe_job = match execution_plan.next() {
-                        Some(ExecutionItem::Execute(executable)) => {
-                            Job::new(&location, &executable)
+                    let item = match waiting.iter().position(|e| !state.waits(e)) {
+                        Some(idx) => Some(ExecutionItem::Execute(waiting.remove(idx))),
+                        None => execution_plan.next(),
+                    };
+                    let executable = match item {
+                        Some(ExecutionItem::Execute(executable)) => executable,
+                        Some(ExecutionItem::Skip(executable, reason)) => {
+                            if state
+                                .skip(&location, &executable, reason, &line_sender)
+                                .is_err()
+                            {
+                                error!("Couldn't send skip message");
+                            }
+                            continue;
+                        }
+                        Some(ExecutionItem::Expect(expected)) => {
+                            if state.output_pending(&expected, &waiting) {
+                                // tells where the result goes, once the code chunk ran
+                                let pending = ExecutionItem::Expect(expected.clone());
+                                if line_sender.send(CommandExecInfo::Output(pending)).is_err() {
+                                    error!("Couldn't send output line, channel maybe closed");
+                                }
+                                waiting_expects.push(expected);
+                            } else {
+                                send_check(&expected, &mut state, &line_sender);
+                            }
+                            continue;
                         }
                         Some(output) => {
                             if line_sender.send(CommandExecInfo::Output(output)).is_err() {
@@ -67,90 +199,97 @@ impl Executor {
                             };
                             continue;
                         }
-                        _ => {
+                        None if !running.is_empty() => {
+                            if finish_next(&mut running, &options, &line_sender, &mut state)
+                                .await
+                                .is_err()
+                            {
+                                break;
+                            }
+                            continue;
+                        }
+                        None if !waiting.is_empty() => {
+                            // only possible with dependencies missing from the plan
+                            for executable in waiting.drain(..) {
+                                let reason = format!(
+                                    "dependencies {:?} didn't run",
+                                    executable.dependencies()
+                                );
+                                if state
+                                    .skip(&location, &executable, reason, &line_sender)
+                                    .is_err()
+                                {
+                                    error!("Couldn't send skip message");
+                                }
+                            }
+                            continue;
+                        }
+                        None => {
                             info!("End of the execution plan");
+                            if line_sender.send(CommandExecInfo::Finished).is_err() {
+                                error!("Couldn't send finish message");
+                            };
                             tokio::select! {
                                 _ = &mut stop_receiver => break,
                             }
                         }
                     };
-                    let maybe_command = maybe_job.as_ref().map(|j| {
-                        let mut command = Command::from(j.get_command());
-                        command.stdin(Stdio::null()).stderr(Stdio::piped()).stdout(
-                            if j.need_stdout {
-                                Stdio::piped()
-                            } else {
-                                Stdio::null()
-                            },
-                        );
-                        command
-                    });
-                    let mut job = if maybe_job.is_some() {
-                        maybe_job.unwrap()
-                    } else {
-                        continue;
-                    };
-                    let with_stdout = job.need_stdout;
-                    let mut command = if maybe_command.is_some() {
-                        maybe_command.unwrap()
-                    } else {
-                        continue;
-                    };
 
-                    // wait for the next task
-                    if let Some(old) = current_task.take() {
-                        old.abort();
-                    }
-
-                    let file_path = match job.write_file() {
-                        Err(e) => {
-                            let response =
-                                CommandExecInfo::Error(format!("failed to write file: {}", e));
-                            match line_sender.send(response) {
-                                Err(_) => break,
-                                _ => continue,
+                    // wait for the code chunks running before this one, so that it
+                    // keeps its place and their failures are known, unless it's a
+                    // `parallel` one waiting for its dependencies
+                    if !executable.is_parallel() || !state.waits(&executable) {
+                        while !state.can_start(&executable, options.jobs) {
+                            if finish_next(&mut running, &options, &line_sender, &mut state)
+                                .await
+                                .is_err()
+                            {
+                                break 'plan;
                             }
+                            check_ready_outputs(
+                                &mut waiting_expects,
+                                &waiting,
+                                &mut state,
+                                &line_sender,
+                            );
                         }
-                        Ok(f) => f,
-                    };
-                    let path_str = String::from(file_path.clone().to_string_lossy());
-                    command.arg(path_str);
-                    let child = match start_task(&mut command) {
-                        Err(e) => {
-                            let response = CommandExecInfo::Error(format!(
-                                "failed to start task: {} job: {}",
-                                e, job
-                            ));
-                            match line_sender.send(response) {
-                                Err(_) => break,
-                                _ => continue,
-                            }
+                    }
+                    if let Some(failure) =
+                        state.first_failure.as_ref().filter(|_| options.fail_fast)
+                    {
+                        let reason = format!("stopped after the failure of {failure}");
+                        if state
+                            .skip(&location, &executable, reason, &line_sender)
+                            .is_err()
+                        {
+                            error!("Couldn't send skip message");
                         }
-                        Ok(child) => child,
-                    };
-
-                    if line_sender.send(CommandExecInfo::Start).is_err() {
-                        error!("Couldn't send start message");
-                    };
-
-                    current_task = Some(tokio::spawn(execute_task(
-                        child,
-                        with_stdout,
-                        line_sender.clone(),
-                    )));
-
-                    // Wait for the current task to finish
-                    let response = match task_result(&mut current_task).await {
-                        Err(e) => CommandExecInfo::Error(format!("failed to execute task: {}", e)),
-                        Ok(status) => CommandExecInfo::End { status },
-                    };
-
-                    if line_sender.send(response).is_err() {
-                        break;
+                        continue;
                     }
-
-                    if !keep_files && job.remove_file().is_err() {
-                        error!("Couldn't remove the job file");
+                    if let Some(reason) = state.blocked_by(&executable) {
+                        if state
+                            .skip(&location, &executable, reason, &line_sender)
+                            .is_err()
+                        {
+                            error!("Couldn't send skip message");
+                        }
+                        continue;
+                    }
+                    if state.waits(&executable) {
+                        debug!("{:?} waits for its dependencies", executable.id());
+                        waiting.push(executable);
+                        continue;
+                    }
+                    if start_job(
+                        &location,
+                        &executable,
+                        &options,
+                        &mut running,
+                        &line_sender,
+                        &mut state,
+                    )
+                    .is_err()
+                    {
                         break;
                     }
                 }
@@ -173,26 +312,611 @@ impl Executor {
     }
 }
 
-async fn task_result(
-    task: &mut Option<JoinHandle<Result<Option<ExitStatus>>>>,
-) -> Result<Option<ExitStatus>> {
-    match task {
-        Some(handle) => handle.await.unwrap(),
-        None => match AlwaysPending.await {},
+/// the attributes setting the environment and the working directory of a process
+type Settings = Vec<(&'static str, String)>;
+
+/// what is known of the code chunks already processed
+#[derive(Default)]
+struct RunState {
+    /// stdout of the executed code chunks, by id, none for the code
+    /// chunks which didn't run to their end
+    outputs: HashMap<String, (ChunkRef, Option<String>)>,
+    /// ids of the skipped code chunks
+    skipped: HashSet<String>,
+    /// ids of the code chunks which failed
+    failed: HashSet<String>,
+    /// ids of the running code chunks, with whether they're `parallel`
+    running: HashMap<String, bool>,
+    /// what failed first, a code chunk or an expected output block
+    first_failure: Option<String>,
+    /// the values captured from the stdout of the code chunks, in order
+    captured: Vec<(String, String)>,
+    /// the interpreter sessions started by the code chunks, by name, with
+    /// their interpreter and the process settings of their first code chunk
+    sessions: HashMap<String, (Interpreter, Settings, Arc<Mutex<Session>>)>,
+}
+
+impl RunState {
+    /// the reason not to run a code chunk, when one of its
+    /// dependencies failed or was skipped, and none of its variants ran
+    fn blocked_by(&self, executable: &Executable) -> Option<String> {
+        executable.dependencies().iter().find_map(|dep| {
+            if self.failed.contains(dep) {
+                Some(format!("dependency `{dep}` failed"))
+            } else if self.skipped.contains(dep)
+                && !self.outputs.contains_key(dep)
+                && !self.running.contains_key(dep)
+            {
+                Some(format!("dependency `{dep}` was skipped"))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// whether some dependencies of a code chunk didn't finish yet
+    fn waits(&self, executable: &Executable) -> bool {
+        executable.dependencies().iter().any(|dep| {
+            self.running.contains_key(dep)
+                || !self.outputs.contains_key(dep)
+                    && !self.skipped.contains(dep)
+                    && !self.failed.contains(dep)
+        })
+    }
+
+    /// whether a code chunk can start now: it runs alone, unless it and
+    /// all the running code chunks are `parallel`, within the jobs limit
+    fn can_start(&self, executable: &Executable, jobs: usize) -> bool {
+        self.running.is_empty()
+            || executable.is_parallel()
+                && self.running.values().all(|parallel| *parallel)
+                && self.running.len() < jobs
+    }
+
+    /// whether an expected output block is about a code chunk
+    /// which is waiting or running
+    fn output_pending(&self, expected: &Executable, waiting: &[Executable]) -> bool {
+        let for_id = match expected
+            .code_chunk
+            .as_ref()
+            .and_then(|c| c.attributes.for_id.as_deref())
+        {
+            Some(for_id) => for_id,
+            None => return false,
+        };
+        self.running.contains_key(for_id) || waiting.iter().any(|e| e.id() == Some(for_id))
+    }
+
+    /// the session running a job, started with its first job
+    fn session(&mut self, name: &str, job: &Job) -> Result<Arc<Mutex<Session>>> {
+        let lang = job
+            .executable
+            .code_chunk
+            .as_ref()
+            .and_then(|c| c.lang.as_deref())
+            .unwrap_or_default();
+        let interpreter = Interpreter::for_lang(lang).ok_or_else(|| {
+            anyhow!("the sessions run bash, python or node code chunks, not `{lang}` ones")
+        })?;
+        let settings = job
+            .executable
+            .code_chunk
+            .as_ref()
+            .map(|c| c.attributes.process_settings())
+            .unwrap_or_default();
+        if let Some((running, started_with, session)) = self.sessions.get(name) {
+            if *running != interpreter {
+                bail!("the session `{name}` runs {running}, not the `{lang}` code chunks");
+            }
+            // the process of the session keeps the settings it started with
+            let changed: Vec<&str> = settings
+                .iter()
+                .zip(started_with)
+                .filter(|(setting, first)| setting != first)
+                .map(|((name, _), _)| *name)
+                .collect();
+            if !changed.is_empty() {
+                bail!(
+                    "the `{}` of the code chunk differ from the ones the session `{name}` started with, its code chunks share the environment and the working directory of its process",
+                    changed.join("`, `")
+                );
+            }
+            return Ok(session.clone());
+        }
+        let session = Arc::new(Mutex::new(Session::start(name, interpreter, job)?));
+        self.sessions
+            .insert(name.to_string(), (interpreter, settings, session.clone()));
+        Ok(session)
+    }
+
+    /// record a failed code chunk, without output unless it exited
+    fn fail(&mut self, chunk: &ChunkRef) {
+        self.outputs
+            .entry(chunk.id.clone())
+            .or_insert_with(|| (chunk.clone(), None));
+        self.failed.insert(chunk.id.clone());
+        self.first_failure
+            .get_or_insert_with(|| format!("`{}`", chunk.id));
+    }
+
+    /// record a skipped code chunk and tell it
+    fn skip(
+        &mut self,
+        location: &JobLocation,
+        executable: &Executable,
+        reason: String,
+        line_sender: &LineSender,
+    ) -> Result<()> {
+        let chunk = ChunkRef::new(location, executable);
+        self.skipped.insert(chunk.id.clone());
+        line_sender.send(CommandExecInfo::Skipped { chunk, reason })?;
+        Ok(())
+    }
+}
+
+/// start the job of a code chunk in the set of running tasks
+/// and send its start.
+///
+/// Returns an error when the channel is closed.
+fn start_job(
+    location: &JobLocation,
+    executable: &Executable,
+    options: &ExecutorOptions,
+    running: &mut JoinSet<FinishedTask>,
+    line_sender: &LineSender,
+    state: &mut RunState,
+) -> Result<()> {
+    if let Some(error) = executable.code_chunk.as_ref().and_then(|c| c.error.clone()) {
+        let chunk = ChunkRef::new(location, executable);
+        state.fail(&chunk);
+        line_sender.send(CommandExecInfo::Error {
+            chunk: Some(chunk),
+            message: error,
+        })?;
+        return Ok(());
+    }
+    let mut job = match Job::new(location, executable, options) {
+        Some(job) => job,
+        None => return Ok(()),
+    };
+    job.use_captured(&state.captured);
+    let chunk = ChunkRef::new(location, executable);
+    let attributes = executable
+        .code_chunk
+        .as_ref()
+        .map(|c| c.attributes.clone())
+        .unwrap_or_default();
+    if job.command.is_empty() && job.session.is_none() {
+        let lang = executable
+            .code_chunk
+            .as_ref()
+            .and_then(|c| c.lang.as_deref())
+            .unwrap_or_default();
+        // a block of data, or of a language without command, is only shown,
+        // unless it has an id to run it
+        if attributes.id.is_none() || attributes.generated_id {
+            let reason = format!("no command for `{lang}`");
+            return state.skip(location, executable, reason, line_sender);
+        }
+        state.fail(&chunk);
+        line_sender.send(CommandExecInfo::Error {
+            chunk: Some(chunk),
+            message: format!(
+                "no command to run the `{lang}` code chunk, set its `cmd` or add `{lang}` to the `[languages]` of gem.toml"
+            ),
+        })?;
+        return Ok(());
+    }
+    let (timeout, retry_delay) = match (attributes.timeout(), attributes.retry_delay()) {
+        (Ok(timeout), Ok(retry_delay)) => (timeout.or(options.timeout), retry_delay),
+        (Err(e), _) | (_, Err(e)) => {
+            state.fail(&chunk);
+            line_sender.send(CommandExecInfo::Error {
+                chunk: Some(chunk),
+                message: e.to_string(),
+            })?;
+            return Ok(());
+        }
+    };
+
+    let session = match job.session.clone() {
+        Some(name) => match state.session(&name, &job) {
+            Ok(session) => Some(session),
+            Err(e) => {
+                state.fail(&chunk);
+                line_sender.send(CommandExecInfo::Error {
+                    chunk: Some(chunk),
+                    message: e.to_string(),
+                })?;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    // without `as_file`, the code goes through stdin or an inline argument
+    let file_path = match job.as_file.then(|| job.write_file()).transpose() {
+        Err(e) => {
+            state.fail(&chunk);
+            line_sender.send(CommandExecInfo::Error {
+                chunk: Some(chunk),
+                message: format!("failed to write file: {}", e),
+            })?;
+            return Ok(());
+        }
+        Ok(f) => f,
+    };
+    // the build phase starts first, the command runs once it passes,
+    // and a session already runs
+    let spawned = if session.is_some() {
+        Ok(None)
+    } else if job.builds() {
+        spawn_build(&job).map(Some)
+    } else {
+        spawn_job(&job, file_path.as_deref()).map(Some)
+    };
+    let child = match spawned {
+        Err(e) => {
+            state.fail(&chunk);
+            line_sender.send(CommandExecInfo::Error {
+                chunk: Some(chunk),
+                message: e.to_string(),
+            })?;
+            return Ok(());
+        }
+        Ok(child) => child,
+    };
+
+    let started = Instant::now();
+    let deadline = Deadline::new(timeout, started);
+    line_sender.send(CommandExecInfo::Start {
+        chunk: chunk.clone(),
+    })?;
+    state
+        .running
+        .insert(chunk.id.clone(), executable.is_parallel());
+
+    let line_sender = line_sender.clone();
+    running.spawn(async move {
+        let mut child = child;
+        let build = if job.builds() { child.take() } else { None };
+        if let Some(build) = build {
+            // the compiler output is always shown
+            let build =
+                execute_task(build, true, chunk.clone(), line_sender.clone(), deadline).await;
+            let status = match build {
+                Ok(TaskEnd::Exited(out)) => out.status,
+                other => return (job, chunk, started, other),
+            };
+            if !status.is_some_and(|s| s.success()) {
+                return (job, chunk, started, Ok(TaskEnd::BuildFailed(status)));
+            }
+            let built = CommandExecInfo::Built {
+                chunk: chunk.clone(),
+                status,
+                passed: true,
+                duration: started.elapsed(),
+            };
+            if let Err(e) = line_sender.send(built) {
+                return (job, chunk, started, Err(e.into()));
+            }
+            child = match spawn_job(&job, file_path.as_deref()) {
+                Ok(child) => Some(child),
+                Err(e) => return (job, chunk, started, Err(e)),
+            };
+        }
+        // retries only run the command again, the build is kept
+        let mut attempt = 1;
+        loop {
+            let output = match (&session, child.take(), &file_path) {
+                (Some(session), _, Some(file_path)) => {
+                    let mut session = session.lock().await;
+                    run_in_session(
+                        &mut session,
+                        &job,
+                        file_path,
+                        &chunk,
+                        &line_sender,
+                        deadline,
+                    )
+                    .await
+                }
+                (None, Some(child), _) => {
+                    execute_task(
+                        child,
+                        job.need_stdout,
+                        chunk.clone(),
+                        line_sender.clone(),
+                        deadline,
+                    )
+                    .await
+                }
+                _ => Err(anyhow!("nothing to run job {}", job)),
+            };
+            let reason = match &output {
+                Ok(TaskEnd::Exited(out)) if job.passed(out.status, out.stderr_lines) => None,
+                Ok(TaskEnd::Exited(out)) => Some(match out.status {
+                    Some(status) if status.success() || job.allow_errors => {
+                        "warnings on stderr".to_string()
+                    }
+                    Some(status) => status.to_string(),
+                    None => "no exit status".to_string(),
+                }),
+                Ok(TaskEnd::TimedOut(timeout)) => Some(timed_out(timeout)),
+                Ok(TaskEnd::BuildFailed(_)) => None,
+                Err(e) => Some(e.to_string()),
+            };
+            // the attempts stop once the timeout is spent
+            let spent = deadline.is_some_and(|d| d.remaining().is_zero());
+            let reason = match reason {
+                Some(reason) if attempt <= attributes.retries && !spent => reason,
+                // the last attempt decides
+                _ => return (job, chunk, started, output),
+            };
+            let retry = CommandExecInfo::Retry {
+                chunk: chunk.clone(),
+                attempt,
+                reason,
+                delay: retry_delay,
+            };
+            if line_sender.send(retry).is_err() {
+                return (job, chunk, started, output);
+            }
+            tokio::time::sleep(retry_delay).await;
+            attempt += 1;
+            if session.is_none() {
+                child = match spawn_job(&job, file_path.as_deref()) {
+                    Ok(child) => Some(child),
+                    Err(e) => return (job, chunk, started, Err(e)),
+                };
+            }
+        }
+    });
+    Ok(())
+}
+
+/// start the command running the code of a job, writing
+/// the code to its stdin when it reads it there
+fn spawn_job(job: &Job, file_path: Option<&Path>) -> Result<Child> {
+    let mut command = job_command(job, file_path)?;
+    let mut child = start_task(&mut command)
+        .map_err(|e| anyhow!("failed to start task: {} job: {}", e, job))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let code = job.executable.code.clone();
+        // written aside, the command may read its stdin while writing its output
+        tokio::spawn(async move {
+            if let Err(e) = stdin.write_all(code.as_bytes()).await {
+                debug!("the code wasn't fully written to stdin: {}", e);
+            }
+        });
+    }
+    Ok(child)
+}
+
+/// run the build file of a job in its session, killing the
+/// session when the job runs past its deadline
+async fn run_in_session(
+    session: &mut Session,
+    job: &Job,
+    file_path: &Path,
+    chunk: &ChunkRef,
+    line_sender: &LineSender,
+    deadline: Option<Deadline>,
+) -> Result<TaskEnd> {
+    let run = session.run(file_path, job.need_stdout, chunk, line_sender);
+    let output = match deadline {
+        None => run.await,
+        Some(deadline) => match tokio::time::timeout(deadline.remaining(), run).await {
+            Ok(output) => output,
+            Err(_) => {
+                session.kill();
+                return Ok(TaskEnd::TimedOut(deadline.timeout));
+            }
+        },
+    };
+    output.map(TaskEnd::Exited)
+}
+
+/// start the command compiling the build file of a job
+fn spawn_build(job: &Job) -> Result<Child> {
+    let mut command = Command::from(job.get_build_command()?);
+    command
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped());
+    start_task(&mut command).map_err(|e| anyhow!("failed to start the build: {} job: {}", e, job))
+}
+
+/// the command running the code of a job: its build file, or its code
+/// after the inline flag, or else the command reading the code on stdin.
+/// A compiled job runs its binary, with its build file in placeholders only
+fn job_command(job: &Job, file_path: Option<&Path>) -> Result<Command> {
+    let mut command = Command::from(job.get_command()?);
+    command
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .stdout(if job.need_stdout {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        });
+    match (file_path, &job.inline_flag) {
+        (Some(_), _) if job.builds() => {}
+        (Some(file_path), _) => {
+            command.arg(file_path.to_string_lossy().as_ref());
+        }
+        (None, Some(flag)) => {
+            command.arg(flag).arg(&job.executable.code);
+        }
+        (None, None) => {
+            command.stdin(Stdio::piped());
+        }
+    }
+    Ok(command)
+}
+
+/// wait for the next running task to finish, then record and send its end.
+///
+/// Returns an error when the channel is closed.
+async fn finish_next(
+    running: &mut JoinSet<FinishedTask>,
+    options: &ExecutorOptions,
+    line_sender: &LineSender,
+    state: &mut RunState,
+) -> Result<()> {
+    let (job, chunk, started, output) = match running.join_next().await {
+        Some(finished) => finished?,
+        None => return Ok(()),
+    };
+    state.running.remove(&chunk.id);
+    let response = match output {
+        Err(e) => {
+            state.fail(&chunk);
+            CommandExecInfo::Error {
+                chunk: Some(chunk),
+                message: format!("failed to execute task: {}", e),
+            }
+        }
+        Ok(TaskEnd::TimedOut(timeout)) => {
+            state.fail(&chunk);
+            CommandExecInfo::TimedOut {
+                chunk,
+                timeout,
+                duration: started.elapsed(),
+            }
+        }
+        Ok(TaskEnd::BuildFailed(status)) => {
+            state.fail(&chunk);
+            CommandExecInfo::Built {
+                chunk,
+                status,
+                passed: false,
+                duration: started.elapsed(),
+            }
+        }
+        Ok(TaskEnd::Exited(output)) => {
+            let passed = job.passed(output.status, output.stderr_lines);
+            if !passed {
+                state.fail(&chunk);
+            }
+            let stdout = output.stdout.join("\n");
+            let captured = if passed {
+                job.capture(&stdout)
+            } else {
+                Ok(None)
+            };
+            state
+                .outputs
+                .insert(chunk.id.clone(), (chunk.clone(), Some(stdout)));
+            match captured {
+                Ok(captured) => {
+                    if let Some((name, value)) = captured {
+                        state.captured.push((name.clone(), value.clone()));
+                        line_sender.send(CommandExecInfo::Captured {
+                            chunk: chunk.clone(),
+                            name,
+                            value,
+                        })?;
+                    }
+                    CommandExecInfo::End {
+                        chunk,
+                        status: output.status,
+                        passed,
+                        duration: started.elapsed(),
+                    }
+                }
+                Err(e) => {
+                    state.fail(&chunk);
+                    CommandExecInfo::Error {
+                        chunk: Some(chunk),
+                        message: e.to_string(),
+                    }
+                }
+            }
+        }
+    };
+    line_sender.send(response)?;
+
+    if !options.keep_files && job.remove_file().is_err() {
+        error!("Couldn't remove the job file");
     }
+    Ok(())
 }
 
-/// A future that will never resolve
-struct AlwaysPending;
+/// check the expected output blocks whose code chunk finished since they were met
+fn check_ready_outputs(
+    waiting_expects: &mut Vec<Executable>,
+    waiting: &[Executable],
+    state: &mut RunState,
+    line_sender: &LineSender,
+) {
+    let (ready, still_waiting) = waiting_expects
+        .drain(..)
+        .partition(|e| !state.output_pending(e, waiting));
+    *waiting_expects = still_waiting;
+    for expected in ready {
+        send_check(&expected, state, line_sender);
+    }
+}
 
-impl std::future::Future for AlwaysPending {
-    type Output = std::convert::Infallible;
+/// check an expected output block, record its failure and send the result
+fn send_check(expected: &Executable, state: &mut RunState, line_sender: &LineSender) {
+    let response = match check_output(expected, state) {
+        Some(response) => response,
+        None => return,
+    };
+    if matches!(
+        response,
+        CommandExecInfo::Assertion { passed: false, .. } | CommandExecInfo::Error { .. }
+    ) {
+        state.first_failure.get_or_insert_with(|| {
+            format!("the output block at line {}", expected.position.start + 1)
+        });
+    }
+    if line_sender.send(response).is_err() {
+        error!("Couldn't send assertion message");
+    };
+}
 
-    fn poll(
-        self: std::pin::Pin<&mut Self>,
-        _cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Self::Output> {
-        std::task::Poll::Pending
+/// compare an expected output block with the captured stdout of its code chunk,
+/// the block failing with the code chunk when it didn't run to its end.
+///
+/// Returns nothing when the code chunk was skipped.
+fn check_output(expected: &Executable, state: &RunState) -> Option<CommandExecInfo> {
+    let attributes = &expected.code_chunk.as_ref()?.attributes;
+    let for_id = attributes.for_id.as_ref()?;
+    match state.outputs.get(for_id) {
+        Some((chunk, actual)) => {
+            let result = match actual {
+                Some(actual) => expected_output::check(attributes.matching, &expected.code, actual),
+                None => Err(
+                    "the code chunk didn't run to its end, there's no output to compare"
+                        .to_string(),
+                ),
+            };
+            Some(CommandExecInfo::Assertion {
+                chunk: chunk.clone(),
+                expected: expected.position.clone(),
+                matching: attributes.matching,
+                passed: result.is_ok(),
+                diff: result.err(),
+            })
+        }
+        None if state.skipped.contains(for_id) => {
+            debug!(
+                "code chunk {} was skipped, its output isn't checked",
+                for_id
+            );
+            None
+        }
+        None => Some(CommandExecInfo::Error {
+            chunk: None,
+            message: format!(
+                "the output block at line {} expects the output of `{}`, which didn't run before",
+                expected.position.start + 1,
+                for_id
+            ),
+        }),
     }
 }
 
@@ -201,19 +925,45 @@ fn start_task(command: &mut Command) -> std::io::Result<Child> {
     command.kill_on_drop(true).spawn()
 }
 
-/// Send all lines in the process' output
+/// Send all lines in the process' output, killing the process and
+/// its descendants when it runs past the deadline
 async fn execute_task(
+    child: Child,
+    with_stdout: bool,
+    chunk: ChunkRef,
+    line_sender: LineSender,
+    deadline: Option<Deadline>,
+) -> Result<TaskEnd> {
+    let group = ProcessGroup::new(child.id());
+    // the child is killed when the task is dropped
+    let task = wait_task(child, with_stdout, chunk, line_sender);
+    let output = match deadline {
+        None => task.await,
+        Some(deadline) => match tokio::time::timeout(deadline.remaining(), task).await {
+            Ok(output) => output,
+            Err(_) => {
+                group.kill();
+                return Ok(TaskEnd::TimedOut(deadline.timeout));
+            }
+        },
+    };
+    output.map(TaskEnd::Exited)
+}
+
+/// Send all lines in the process' output and wait for its exit
+async fn wait_task(
     mut child: Child,
     with_stdout: bool,
+    chunk: ChunkRef,
     line_sender: LineSender,
-) -> Result<Option<ExitStatus>> {
+) -> Result<TaskOutput> {
     let stderr = child
         .stderr
         .take()
         .ok_or_else(|| anyhow!("child missing stderr"))?;
 
     let stderr_sender = line_sender.clone();
-    let stderr = stream_consumer(stderr, CommandStream::StdErr, stderr_sender);
+    let stderr = stream_consumer(stderr, CommandStream::StdErr, chunk.clone(), stderr_sender);
 
     let stdout = if with_stdout {
         let stdout = child
@@ -224,6 +974,7 @@ async fn execute_task(
         Some(stream_consumer(
             stdout,
             CommandStream::StdOut,
+            chunk,
             stdout_sender,
         ))
     } else {
@@ -231,37 +982,220 @@ async fn execute_task(
     };
 
     // either we wait on both stdout and stderr concurrently, or just stderr.
-    if let Some(stdout) = stdout {
-        tokio::try_join!(stdout, stderr)?;
+    let (stdout, stderr) = if let Some(stdout) = stdout {
+        tokio::try_join!(stdout, stderr)?
     } else {
-        stderr.await?;
-    }
-
-    let status = match child.wait().await {
-        Err(_) => None,
-        Ok(status) => Some(status),
+        (vec![], stderr.await?)
     };
 
-    Ok(status)
+    let status = child.wait().await.ok();
+
+    Ok(TaskOutput {
+        status,
+        stdout,
+        stderr_lines: stderr.len(),
+    })
 }
 
-/// Send all lines in the given stream to the sender.
+/// Send all lines in the given stream to the sender, and return them.
 async fn stream_consumer(
     stream: impl AsyncRead + Unpin,
     origin: CommandStream,
+    chunk: ChunkRef,
     line_sender: LineSender,
-) -> Result<()> {
+) -> Result<Vec<String>> {
     let mut lines = BufReader::new(stream).lines();
+    let mut captured = vec![];
 
     while let Some(line) = lines.next_line().await? {
-        let response = CommandExecInfo::Line(CommandOutputLine {
-            content: line,
-            origin,
-        });
+        captured.push(line.clone());
+        let response = CommandExecInfo::Line {
+            chunk: chunk.clone(),
+            line: CommandOutputLine {
+                content: line,
+                origin,
+            },
+        };
         if line_sender.send(response).is_err() {
             return Err(anyhow!("channel closed"));
         }
     }
 
-    Ok(())
+    Ok(captured)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use uuid::Uuid;
+
+    use crate::executor::command_output::{CommandExecInfo, CommandStream};
+    use crate::executor::execution_plan::ExecutionPlan;
+    use crate::executor::job_location::JobLocation;
+    use crate::executor::{Executor, ExecutorOptions};
+    use crate::report::{ChunkOutcome, RunReport};
+
+    /// run the code chunks of a markdown text in a temporary workspace
+    fn run_md(md: &str) -> RunReport {
+        let workspace_root = std::env::temp_dir().join(format!("gem-{}", Uuid::new_v4()));
+        fs::create_dir_all(&workspace_root).unwrap();
+        let location = JobLocation {
+            path_to_md: Some(workspace_root.join("README.md")),
+            workspace_root: workspace_root.clone(),
+        };
+        let plan = ExecutionPlan::from_md_lines(md.lines());
+        let executor = Executor::new(location, plan, ExecutorOptions::default()).unwrap();
+        let mut report = RunReport::default();
+        for info in executor.line_receiver.iter() {
+            report.record(&info);
+            if matches!(info, CommandExecInfo::Finished) {
+                break;
+            }
+        }
+        executor.die().unwrap();
+        fs::remove_dir_all(workspace_root).unwrap();
+        report
+    }
+
+    fn outcome(report: &RunReport, line: usize) -> &ChunkOutcome {
+        &report
+            .chunks
+            .iter()
+            .find(|c| c.chunk.position.start == line)
+            .unwrap()
+            .outcome
+    }
+
+    #[test]
+    fn chunk_without_build_phase_runs_its_command() {
+        let report = run_md("```bash\necho hi\n```\n");
+
+        let chunk = &report.chunks[0];
+        assert!(matches!(
+            chunk.outcome,
+            ChunkOutcome::Finished { passed: true, .. }
+        ));
+        assert_eq!(chunk.output.content(CommandStream::StdOut), vec!["hi"]);
+    }
+
+    #[test]
+    fn code_without_file() {
+        let stdout = |md: &str| {
+            let report = run_md(md);
+            assert_eq!(report.failures(), 0);
+            report.chunks[0]
+                .output
+                .content(CommandStream::StdOut)
+                .join("\n")
+        };
+        // through stdin
+        let md = "```python {as_file=false}\nimport sys\nprint(repr(sys.argv[0]))\n```\n";
+        assert_eq!(stdout(md), "''");
+        // after the inline flag
+        let md = "```python {as_file=false, inline_flag=-c}\nimport sys\nprint(sys.argv[0])\n```\n";
+        assert_eq!(stdout(md), "-c");
+        let md = "```perl {as_file=false, inline_flag=-e}\nprint \"$0\\n\";\n```\n";
+        assert_eq!(stdout(md), "-e");
+    }
+
+    #[test]
+    fn session_chunks_need_a_file() {
+        let md = "```bash {session=s, as_file=false}\nX=1\n```\n```bash {session=s, as_file=false}\necho \"x=$X\"\n```\n";
+        let report = run_md(md);
+
+        assert_eq!(report.failures(), 0);
+        assert_eq!(
+            report.chunks[1].output.content(CommandStream::StdOut),
+            vec!["x=1"]
+        );
+    }
+
+    #[test]
+    fn blocks_without_command_are_only_shown() {
+        let md =
+            "```json\n{\"a\": 1}\n```\n```sh\necho done\n```\n```toml {id=config}\na = 1\n```\n";
+        let report = run_md(md);
+
+        assert_eq!(
+            outcome(&report, 0),
+            &ChunkOutcome::Skipped("no command for `json`".to_string())
+        );
+        assert!(!outcome(&report, 3).is_failure());
+        assert!(outcome(&report, 6).is_failure());
+        assert_eq!(report.failures(), 1);
+
+        let report = run_md(&md[..md.find("```toml").unwrap()]);
+        assert_eq!(report.failures(), 0);
+    }
+
+    #[test]
+    fn invalid_attributes_fail_the_code_chunk() {
+        let report = run_md("```sh {id=x, retries=abc}\necho hi\n```\n");
+
+        assert!(matches!(
+            outcome(&report, 0),
+            ChunkOutcome::Error(message) if message.starts_with("invalid attributes `{id=x, retries=abc}`")
+        ));
+        assert_eq!(report.failures(), 1);
+    }
+
+    #[test]
+    fn timeout_is_one_budget_for_the_attempts() {
+        let report = run_md("```sh {timeout=500ms, retries=3}\nsleep 5\n```\n");
+
+        let chunk = &report.chunks[0];
+        assert_eq!(
+            chunk.outcome,
+            ChunkOutcome::TimedOut(Duration::from_millis(500))
+        );
+        assert_eq!(chunk.attempts, 1);
+        assert!(chunk.duration >= Duration::from_millis(500));
+        assert!(chunk.duration < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn skipped_variant_of_a_dependency() {
+        let md = "```sh {id=install}\necho linux\n```\n```sh {id=install, eval=false}\necho macos\n```\n```sh {id=test, depends_on=[install]}\necho test\n```\n";
+        let report = run_md(md);
+
+        assert!(!outcome(&report, 0).is_failure());
+        assert_eq!(
+            outcome(&report, 3),
+            &ChunkOutcome::Skipped("eval=false".to_string())
+        );
+        assert!(matches!(
+            outcome(&report, 6),
+            ChunkOutcome::Finished { passed: true, .. }
+        ));
+    }
+
+    #[test]
+    fn output_of_a_failed_chunk() {
+        let md = "```sh {id=a, timeout=100ms}\nsleep 5\n```\n```output {for=a}\n```\n";
+        let report = run_md(md);
+
+        let chunk = &report.chunks[0];
+        assert!(matches!(chunk.outcome, ChunkOutcome::TimedOut(_)));
+        assert_eq!(chunk.assertions.len(), 1);
+        assert_eq!(chunk.assertions[0].expected.start, 3);
+        assert!(report.errors.is_empty());
+        assert_eq!(report.failures(), 1);
+    }
+
+    #[test]
+    fn session_chunks_share_the_process_settings() {
+        let md = "```sh {session=s, env={A=1}}\necho $A\n```\n```sh {session=s, env={A=1}}\necho $A\n```\n```sh {session=s, env={A=2}}\necho $A\n```\n";
+        let report = run_md(md);
+
+        assert!(!outcome(&report, 0).is_failure());
+        assert!(!outcome(&report, 3).is_failure());
+        assert_eq!(
+            outcome(&report, 6),
+            &ChunkOutcome::Error(
+                "the `env` of the code chunk differ from the ones the session `s` started with, its code chunks share the environment and the working directory of its process".to_string()
+            )
+        );
+    }
 }
@@ -0,0 +1,86 @@
+use regex::Regex;
+use similar::TextDiff;
+
+use crate::fenced_attributes::OutputMatch;
+
+/// Check the captured output of a code chunk against the content of an
+/// expected output block.
+///
+/// When they don't match, returns the unified diff from the expected
+/// to the actual output, or the reason the check couldn't be done.
+pub fn check(matching: OutputMatch, expected: &str, actual: &str) -> Result<(), String> {
+    let expected = normalize(expected);
+    let actual = normalize(actual);
+    let matches = match matching {
+        OutputMatch::Exact => expected == actual,
+        OutputMatch::Trimmed => trim(&expected) == trim(&actual),
+        OutputMatch::Regex => {
+            let regex = Regex::new(&format!("^(?:{})$", trim(&expected)))
+                .map_err(|e| format!("invalid regex in the output block: {e}"))?;
+            regex.is_match(trim(&actual).as_str())
+        }
+        OutputMatch::Contains => actual.contains(trim(&expected).as_str()),
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(diff(&expected, &actual))
+    }
+}
+
+/// unified diff from the expected to the actual output
+pub fn diff(expected: &str, actual: &str) -> String {
+    TextDiff::from_lines(expected, actual)
+        .unified_diff()
+        .header("expected", "actual")
+        .to_string()
+}
+
+/// use `\n` as line ending and end the text with a new line
+fn normalize(text: &str) -> String {
+    text.lines().map(|l| format!("{l}\n")).collect()
+}
+
+/// remove the trailing whitespaces of the lines and the blank lines around the text
+fn trim(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    lines.join("\n").trim_matches('\n').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::executor::expected_output::check;
+    use crate::fenced_attributes::OutputMatch;
+
+    #[test]
+    fn exact_match() {
+        assert!(check(OutputMatch::Exact, "a\nb\n", "a\nb").is_ok());
+        assert!(check(OutputMatch::Exact, "a\r\nb\r\n", "a\nb\n").is_ok());
+
+        let diff = check(OutputMatch::Exact, "a\nb\n", "a\nc\n").unwrap_err();
+        assert!(diff.contains("--- expected"));
+        assert!(diff.contains("-b"));
+        assert!(diff.contains("+c"));
+    }
+
+    #[test]
+    fn trimmed_match() {
+        assert!(check(OutputMatch::Trimmed, "\na  \nb\n\n", "a\nb   \n").is_ok());
+        assert!(check(OutputMatch::Trimmed, "a\nb", "a\n\nb").is_err());
+    }
+
+    #[test]
+    fn regex_match() {
+        assert!(check(OutputMatch::Regex, "version \\d+\\.\\d+\n", "version 1.2\n").is_ok());
+        assert!(check(OutputMatch::Regex, "version \\d+\n", "version 1.2\n").is_err());
+        assert!(check(OutputMatch::Regex, "(", "(")
+            .unwrap_err()
+            .starts_with("invalid regex"));
+    }
+
+    #[test]
+    fn contains_match() {
+        assert!(check(OutputMatch::Contains, "installed\n", "a\ninstalled\nb\n").is_ok());
+        assert!(check(OutputMatch::Contains, "installed\n", "failed\n").is_err());
+    }
+}
@@ -1,8 +1,155 @@
-use crate::executor::command_output::CommandOutput;
+use std::io::Write;
 
-/// data of a failed command
+use crate::executor::command_output::{ChunkRef, CommandOutput};
+use crate::report::{ChunkOutcome, ChunkReport, RunReport};
+
+/// how many of the last lines of output are shown for a failed code chunk
+const MAX_OUTPUT_LINES: usize = 20;
+
+/// data of a failed code chunk
 #[derive(Debug)]
 pub struct Failure {
-    pub error_code: i32,
+    pub chunk: ChunkRef,
+    /// why the code chunk failed
+    pub reason: String,
+    /// exit code of the command, when it exited
+    pub error_code: Option<i32>,
     pub output: CommandOutput,
 }
+
+impl Failure {
+    /// the failure of a code chunk, if it failed
+    pub fn from_report(report: &ChunkReport) -> Option<Self> {
+        let reason = report.outcome.failure_reason().or_else(|| {
+            report.failed_assertions().next().map(|a| {
+                format!(
+                    "output doesn't match the block at line {}",
+                    a.expected.start + 1
+                )
+            })
+        })?;
+        let error_code = match &report.outcome {
+            ChunkOutcome::Finished {
+                status: Some(status),
+                ..
+            } => status.code(),
+            ChunkOutcome::BuildFailed(Some(status)) => status.code(),
+            _ => None,
+        };
+        Some(Self {
+            chunk: report.chunk.clone(),
+            reason,
+            error_code,
+            output: report.output.clone(),
+        })
+    }
+}
+
+/// write the failed code chunks of a run with their output,
+/// nothing when all passed
+pub fn write_failures<W: Write>(w: &mut W, report: &RunReport) -> std::io::Result<()> {
+    let failures: Vec<Failure> = report
+        .chunks
+        .iter()
+        .filter_map(Failure::from_report)
+        .collect();
+    if failures.is_empty() && report.errors.is_empty() {
+        return Ok(());
+    }
+    writeln!(w)?;
+    writeln!(
+        w,
+        "Failure: {} code chunk(s) failed, {} error(s)",
+        failures.len(),
+        report.errors.len()
+    )?;
+    for failure in &failures {
+        write!(
+            w,
+            "{}:{} `{}`: {}",
+            failure.chunk.md_file.display(),
+            failure.chunk.position.start + 1,
+            failure.chunk.id,
+            failure.reason
+        )?;
+        match failure.error_code {
+            Some(code) => writeln!(w, " (exit code {code})")?,
+            None => writeln!(w)?,
+        }
+        let lines = &failure.output.lines;
+        let skipped = lines.len().saturating_sub(MAX_OUTPUT_LINES);
+        if skipped > 0 {
+            writeln!(w, "    | ... {skipped} more line(s)")?;
+        }
+        for line in &lines[skipped..] {
+            writeln!(w, "    | {}", line.content)?;
+        }
+    }
+    for (md_file, error) in &report.errors {
+        writeln!(w, "{}: error: {error}", md_file.display())?;
+    }
+    w.flush()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+    use std::path::PathBuf;
+    use std::process::ExitStatus;
+    use std::time::Duration;
+
+    use crate::executor::command_output::{
+        ChunkRef, CommandExecInfo, CommandOutputLine, CommandStream,
+    };
+    use crate::executor::executable::ExecutablePosition;
+    use crate::report::RunReport;
+
+    use super::write_failures;
+
+    fn chunk(id: &str, start: usize) -> ChunkRef {
+        ChunkRef {
+            id: id.to_string(),
+            md_file: PathBuf::from("README.md"),
+            position: ExecutablePosition::new(start, start + 2),
+            generated_id: false,
+        }
+    }
+
+    #[test]
+    fn lists_the_failed_chunks_with_their_output() {
+        let mut report = RunReport::default();
+        for (id, start, code, passed) in [("ok", 0, 0, true), ("ko", 4, 2, false)] {
+            report.record(&CommandExecInfo::Start {
+                chunk: chunk(id, start),
+            });
+            report.record(&CommandExecInfo::Line {
+                chunk: chunk(id, start),
+                line: CommandOutputLine {
+                    content: format!("{id} output"),
+                    origin: CommandStream::StdErr,
+                },
+            });
+            report.record(&CommandExecInfo::End {
+                chunk: chunk(id, start),
+                status: Some(ExitStatus::from_raw(code << 8)),
+                passed,
+                duration: Duration::ZERO,
+            });
+        }
+
+        let mut out = vec![];
+        write_failures(&mut out, &report).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\nFailure: 1 code chunk(s) failed, 0 error(s)\n\
+             README.md:5 `ko`: exit status: 2 (exit code 2)\n    | ko output\n"
+        );
+    }
+
+    #[test]
+    fn nothing_when_all_passed() {
+        let mut out = vec![];
+        write_failures(&mut out, &RunReport::default()).unwrap();
+        assert!(out.is_empty());
+    }
+}
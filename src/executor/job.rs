@@ -1,29 +1,61 @@
 use std::fmt::{Display, Formatter};
-use std::path::PathBuf;
-use std::process::Command;
-use std::{fs, io};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::{env, fs, io};
 
+use anyhow::{anyhow, bail, Result};
+
+use regex::Regex;
 use uuid::Uuid;
 
 use crate::cli::action::Action;
+use crate::executor::env::{expand, read_env_file, substitute};
 use crate::executor::executable::Executable;
 use crate::executor::job_location::JobLocation;
+use crate::executor::ExecutorOptions;
+use crate::fenced_attributes::{Attributes, WorkDir};
 
-static MURABI_BUILD_DIR: &str = ".mrbuild";
+/// default directory of the build files, in the workspace root
+pub static BUILD_DIR: &str = ".mrbuild";
 
 /// One of the possible jobs that markdown-gem can run
 #[derive(Debug, Clone)]
 pub struct Job {
     /// The tokens making the command to execute (first one
-    /// is the executable).
-    /// This vector is guaranteed not empty
-    /// by the PackageConfig::from_path loader
+    /// is the executable), either the `cmd` attribute or
+    /// the command of the code chunk language.
+    /// This vector is empty when there's no command for the code chunk
     pub command: Vec<String>,
 
     /// whether we need to capture stdout too (stderr is
     /// always captured)
     pub need_stdout: bool,
 
+    /// whether the code is written to a build file passed to
+    /// the command, rather than given inline or on stdin
+    pub as_file: bool,
+
+    /// the tokens of the command compiling the build file before the
+    /// job runs, empty when the job has no build phase
+    pub build: Vec<String>,
+
+    /// name of the variable storing the stdout of the job
+    pub capture: Option<String>,
+
+    /// regex of the value to capture in stdout
+    capture_regex: Option<String>,
+
+    /// the values captured by the previous jobs, added to the environment
+    captured: Vec<(String, String)>,
+
+    /// the interpreter session running the job, instead of its command
+    pub session: Option<String>,
+
+    /// the flag giving the code inline to the command, e.g. `-c`,
+    /// the code going to stdin without it when not `as_file`
+    pub inline_flag: Option<String>,
+
+    #[allow(dead_code)]
     /// the optional action to run when there's no
     /// error, warning or test failures
     pub on_success: Option<Action>,
@@ -44,7 +76,22 @@ pub struct Job {
     /// the location of the job
     pub location: JobLocation,
 
-    /// the build file location after it's created
+    /// the directory of the build file, relative to the workspace root
+    build_dir: PathBuf,
+
+    /// where the job runs without `workdir` attribute
+    workdir: WorkDir,
+
+    /// extension of the build file, from the code chunk language
+    extension: Option<String>,
+
+    /// fixed name of the build file, from the `filename` attribute
+    filename: Option<String>,
+
+    /// the directory of the build file after it's created
+    file_dir: Option<PathBuf>,
+
+    /// the build file after it's created
     file_path: Option<PathBuf>,
 
     // TODO: remove after sudo implementation
@@ -54,96 +101,391 @@ pub struct Job {
 }
 
 impl Job {
-    pub fn new(location: &JobLocation, item: &Executable) -> Option<Self> {
+    pub fn new(
+        location: &JobLocation,
+        item: &Executable,
+        options: &ExecutorOptions,
+    ) -> Option<Self> {
         item.code_chunk.as_ref().map(|c| {
             let attributes = c.attributes.clone();
-            let cmd = attributes.cmd.unwrap_or(String::from(""));
-            let command = vec![cmd];
+            let build = match (&attributes.build, &c.lang) {
+                // the session interpreters run the code as is
+                _ if attributes.session.is_some() => vec![],
+                (Some(build), _) => build.clone(),
+                (None, Some(lang)) => options
+                    .languages
+                    .build(lang)
+                    .map(<[_]>::to_vec)
+                    .unwrap_or_default(),
+                (None, None) => vec![],
+            };
+            let command = match (&attributes.cmd, &c.lang) {
+                (Some(cmd), _) => vec![cmd.clone()],
+                (None, Some(lang)) => options
+                    .languages
+                    .get(lang)
+                    .map(<[_]>::to_vec)
+                    .unwrap_or_default(),
+                (None, None) => vec![],
+            };
+            // a compiled code chunk runs its binary by default
+            let command = if command.is_empty() && !build.is_empty() {
+                vec!["{bin}".to_string()]
+            } else {
+                command
+            };
 
             Self {
                 command,
-                need_stdout: attributes.stdout,
+                // the captured value comes from stdout
+                need_stdout: attributes.stdout || attributes.capture.is_some(),
+                // the compiler and the sessions need a file
+                as_file: attributes.as_file || !build.is_empty() || attributes.session.is_some(),
+                build,
+                session: attributes.session.clone(),
+                capture: attributes.capture.clone(),
+                capture_regex: attributes.capture_regex.clone(),
+                captured: vec![],
+                inline_flag: attributes.inline_flag.clone(),
                 on_success: None,
                 allow_warnings: attributes.allow_warnings,
                 allow_errors: attributes.allow_errors,
                 executable: item.clone(),
                 location: location.clone(),
+                build_dir: options.build_dir.clone(),
+                workdir: options.workdir,
+                extension: c
+                    .lang
+                    .as_deref()
+                    .and_then(|lang| options.languages.extension(lang))
+                    .map(String::from),
+                filename: attributes.filename.clone(),
+                file_dir: None,
                 file_path: None,
                 sudo: attributes.with_sudo,
             }
         })
     }
 
-    pub fn get_command(&self) -> Command {
-        let mut tokens = self.command.iter();
+    /// give the job the values captured by the previous jobs, in its
+    /// environment and in place of their `${VAR}` references in its code
+    pub fn use_captured(&mut self, captured: &[(String, String)]) {
+        let lookup = |name: &str| {
+            captured
+                .iter()
+                .rev()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+        self.executable.code = substitute(&self.executable.code, lookup);
+        self.captured = captured.to_vec();
+    }
+
+    /// the variable captured from the stdout of the job, if it has a `capture`
+    pub fn capture(&self, stdout: &str) -> Result<Option<(String, String)>> {
+        let name = match &self.capture {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let value = match &self.capture_regex {
+            Some(regex) => {
+                let regex = Regex::new(regex)
+                    .map_err(|e| anyhow!("invalid capture_regex `{regex}`: {e}"))?;
+                let captures = regex.captures(stdout).ok_or_else(|| {
+                    anyhow!("no match of `{regex}` in the stdout to capture `{name}`")
+                })?;
+                captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map(|m| m.as_str())
+                    .unwrap_or_default()
+            }
+            None => stdout,
+        };
+        Ok(Some((name.clone(), value.trim().to_string())))
+    }
+
+    /// whether the job compiles its build file before running
+    pub fn builds(&self) -> bool {
+        !self.build.is_empty()
+    }
+
+    /// the command running the job, with the environment
+    /// and the arguments of its attributes
+    pub fn get_command(&self) -> Result<Command> {
+        let mut command = self.command_with(&self.command, self.sudo)?;
+        let args = self
+            .executable
+            .code_chunk
+            .as_ref()
+            .and_then(|c| c.attributes.args.as_ref());
+        if let Some(args) = args {
+            for arg in args {
+                command.arg(arg);
+            }
+        }
+        Ok(command)
+    }
+
+    /// the command compiling the build file of the job, with its environment
+    pub fn get_build_command(&self) -> Result<Command> {
+        self.command_with(&self.build, false)
+    }
+
+    /// a command of the job, its `{file}`, `{bin}` and `{dir}` placeholders
+    /// replaced, running in the working directory with the job environment
+    pub fn command_with(&self, tokens: &[String], sudo: bool) -> Result<Command> {
+        let mut tokens = tokens.iter().map(|token| self.fill_placeholders(token));
+        let program = tokens
+            .next()
+            .ok_or_else(|| anyhow!("no command for job {}", self))?;
         // TODO: implement proper sudo handling with pass request
-        let mut command = if self.sudo {
+        let mut command = if sudo {
             let mut sudo = Command::new("sudo");
-            sudo.arg(tokens.next().unwrap());
+            sudo.arg(program);
             sudo
         } else {
-            Command::new(tokens.next().unwrap())
+            Command::new(program)
         };
-        // let mut command = Command::new(tokens.next().unwrap());
-        command.current_dir(self.location.workspace_root.clone());
-        let (args, path) = self
+        command.args(tokens);
+        // a process group of its own, to kill the descendants on timeout
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let attributes = self
             .executable
             .code_chunk
             .clone()
-            .map(|c| (c.attributes.args, c.attributes.path))
-            .unwrap_or((None, None));
+            .map(|c| c.attributes)
+            .unwrap_or_default();
+        command.current_dir(self.working_dir(&attributes)?);
 
-        if path.is_some() {
-            command.env("PATH", path.unwrap());
-        }
-        if args.is_some() {
-            for arg in args.unwrap() {
-                command.arg(arg);
+        // the captured variables, then the ones of the env file and of the `env`
+        // attribute, `${VAR}` referring to them or to the markdown-gem environment
+        let mut variables: Vec<(String, String)> = self.captured.clone();
+        if let Some(env_file) = &attributes.env_file {
+            let env_file = self.location.workspace_root.join(env_file);
+            for (key, value) in read_env_file(&env_file)? {
+                let value = expand(&value, |name| lookup(&variables, name));
+                variables.push((key, value));
             }
         }
-        command
+        let env_variables: Vec<(String, String)> = attributes
+            .env
+            .iter()
+            .flatten()
+            .map(|(key, value)| {
+                let value = expand(value, |name| lookup(&variables, name));
+                (key.clone(), value)
+            })
+            .collect();
+        variables.extend(env_variables);
+        command.envs(variables.iter().map(|(k, v)| (k, v)));
+
+        if attributes.path.is_some()
+            || attributes.path_prepend.is_some()
+            || attributes.path_append.is_some()
+        {
+            let path = attributes
+                .path
+                .map(|path| expand(&path, |name| lookup(&variables, name)))
+                .or_else(|| lookup(&variables, "PATH"))
+                .unwrap_or_default();
+            let expand_all = |dirs: Option<Vec<String>>| -> Vec<PathBuf> {
+                dirs.unwrap_or_default()
+                    .iter()
+                    .map(|dir| PathBuf::from(expand(dir, |name| lookup(&variables, name))))
+                    .collect()
+            };
+            let dirs = expand_all(attributes.path_prepend)
+                .into_iter()
+                .chain(env::split_paths(&path))
+                .chain(expand_all(attributes.path_append));
+            command.env("PATH", env::join_paths(dirs)?);
+        }
+        Ok(command)
+    }
+
+    /// replace the placeholders of a command token by the paths
+    /// of the build file, of its binary and of its directory
+    fn fill_placeholders(&self, token: &str) -> String {
+        let file = match &self.file_path {
+            Some(file) => file,
+            None => return token.to_string(),
+        };
+        let mut bin = file.with_extension(env::consts::EXE_EXTENSION);
+        if &bin == file {
+            bin = file.with_extension("bin");
+        }
+        let dir = file.parent().unwrap_or(file);
+        token
+            .replace("{file}", &file.to_string_lossy())
+            .replace("{bin}", &bin.to_string_lossy())
+            .replace("{dir}", &dir.to_string_lossy())
+    }
+
+    /// the directory where the job runs, which must exist
+    fn working_dir(&self, attributes: &Attributes) -> Result<PathBuf> {
+        let base = match attributes.workdir.unwrap_or(self.workdir) {
+            WorkDir::Markdown => self
+                .location
+                .path_to_md
+                .as_ref()
+                .and_then(|md| md.parent())
+                .map(|dir| self.location.workspace_root.join(dir))
+                .unwrap_or_else(|| self.location.workspace_root.clone()),
+            WorkDir::Workspace => self.location.workspace_root.clone(),
+        };
+        let dir = match &attributes.cwd {
+            Some(cwd) => base.join(expand(cwd, |name| env::var(name).ok())),
+            None => base,
+        };
+        if !dir.is_dir() {
+            bail!("the working directory {} doesn't exist", dir.display());
+        }
+        Ok(dir)
     }
 
-    /// create and write the job file
+    /// whether a run of the job passed: a non-zero exit status fails unless
+    /// errors are allowed, lines on stderr fail unless warnings are allowed
+    pub fn passed(&self, status: Option<ExitStatus>, stderr_lines: usize) -> bool {
+        let exited = status.is_some_and(|s| s.success() || self.allow_errors);
+        exited && (self.allow_warnings || stderr_lines == 0)
+    }
+
+    /// create and write the job file, in a directory of its own so that
+    /// the fixed file names of the code chunks don't collide
     pub fn write_file(&mut self) -> io::Result<PathBuf> {
-        let name = self
+        let id = self
             .executable
             .code_chunk
             .as_ref()
-            .map(|c| c.clone().attributes.id)
-            .flatten()
+            .and_then(|c| c.clone().attributes.id)
             .unwrap_or_else(|| Uuid::new_v4().to_string());
-
-        let build_dir = self.location.clone().workspace_root;
-        let mut build_dir = PathBuf::from(format!(
-            "{}/{}",
-            String::from(build_dir.to_string_lossy()),
-            MURABI_BUILD_DIR
-        ));
-        if !build_dir.exists() {
-            fs::create_dir(&build_dir)?;
+        let name = match (&self.filename, &self.extension) {
+            (Some(filename), _) => filename.clone(),
+            (None, Some(extension)) => format!("{id}.{extension}"),
+            (None, None) => id.clone(),
+        };
+        if !Path::new(&name)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the file name `{name}` must be relative, without `..`"),
+            ));
         }
 
-        build_dir.push(name);
-        fs::write(&build_dir, &self.executable.code)?;
+        let dir = self.location.workspace_root.join(&self.build_dir).join(id);
+        let path = dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, &self.executable.code)?;
 
-        self.file_path = Some(PathBuf::from(&build_dir));
-        Ok(build_dir)
+        self.file_dir = Some(dir);
+        self.file_path = Some(path.clone());
+        Ok(path)
     }
 
-    /// remove the job file
+    /// remove the job file, with its directory
     pub fn remove_file(&self) -> io::Result<()> {
-        if self.file_path.is_some() {
-            let path = self.file_path.as_ref().unwrap();
-            fs::remove_file(path)
-        } else {
-            Ok(())
+        match &self.file_dir {
+            Some(dir) => fs::remove_dir_all(dir),
+            None => Ok(()),
         }
     }
 }
 
+/// the value of a variable, set by the job or else in the markdown-gem environment
+fn lookup(variables: &[(String, String)], name: &str) -> Option<String> {
+    variables
+        .iter()
+        .rev()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.clone())
+        .or_else(|| env::var(name).ok())
+}
+
 impl Display for Job {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use crate::executor::executable::{Executable, ExecutablePosition};
+    use crate::executor::job::{Job, BUILD_DIR};
+    use crate::executor::job_location::JobLocation;
+    use crate::executor::ExecutorOptions;
+    use crate::fenced_attributes::{Attributes, CodeChunk};
+
+    /// write the file of a code chunk, in a new workspace root
+    fn write_file(lang: &str, attributes: &str) -> (PathBuf, io::Result<PathBuf>) {
+        let workspace_root = std::env::temp_dir().join(format!("gem-{}", Uuid::new_v4()));
+        fs::create_dir_all(&workspace_root).unwrap();
+        let location = JobLocation {
+            path_to_md: Some(workspace_root.join("README.md")),
+            workspace_root: workspace_root.clone(),
+        };
+        let code_chunk = CodeChunk {
+            lang: Some(lang.to_string()),
+            attributes: Attributes::parse(attributes).unwrap(),
+            error: None,
+        };
+        let executable = Executable::new(
+            ExecutablePosition::new(0, 2),
+            Some(code_chunk),
+            "echo hi".to_string(),
+        );
+        let mut job = Job::new(&location, &executable, &ExecutorOptions::default()).unwrap();
+        let path = job.write_file();
+        (workspace_root, path)
+    }
+
+    #[test]
+    fn file_named_after_the_id_and_extension() {
+        let (root, path) = write_file("bash", "{id=greet}");
+        let path = path.unwrap();
+        assert_eq!(path, root.join(BUILD_DIR).join("greet").join("greet.sh"));
+        assert_eq!(fs::read_to_string(path).unwrap(), "echo hi");
+
+        let (root, path) = write_file("python", "{id=greet}");
+        assert_eq!(
+            path.unwrap(),
+            root.join(BUILD_DIR).join("greet").join("greet.py")
+        );
+    }
+
+    #[test]
+    fn file_named_by_the_filename_attribute() {
+        let (root, path) = write_file("bash", "{id=greet, filename=\"src/main.sh\"}");
+        assert_eq!(
+            path.unwrap(),
+            root.join(BUILD_DIR)
+                .join("greet")
+                .join("src")
+                .join("main.sh")
+        );
+    }
+
+    #[test]
+    fn filename_stays_in_the_build_directory() {
+        for filename in ["../main.sh", "/tmp/main.sh", "src/../../main.sh"] {
+            let (_, path) = write_file("bash", &format!("{{id=greet, filename=\"{filename}\"}}"));
+            let err = path.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(
+                err.to_string(),
+                format!("the file name `{filename}` must be relative, without `..`")
+            );
+        }
+    }
+}
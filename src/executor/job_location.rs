@@ -1,5 +1,9 @@
 use anyhow::Result;
-use std::{env, fmt, path::PathBuf};
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+use std::{
+    env, fmt,
+    path::{Path, PathBuf},
+};
 
 use crate::cli::args::Args;
 
@@ -31,6 +35,8 @@ impl JobLocation {
         ) {
             // its a folder and it exists
             (true, _, true) => {
+                // the commands run in the workspace, where a relative path is wrong
+                workspace_root = workspace_root.canonicalize()?;
                 let mut maybe_md_path = workspace_root.clone();
                 maybe_md_path.push("README.md");
                 Some(maybe_md_path)
@@ -62,6 +68,45 @@ impl JobLocation {
             workspace_root,
         })
     }
+
+    /// find the locations of all the MD files in the directory tree of the path,
+    /// honouring `.gitignore` and the include/exclude globs, sorted by path
+    pub fn find_all(args: &Args) -> Result<Vec<Self>> {
+        let location = Self::new(args)?;
+        let root = location.workspace_root.clone();
+        if args.path.as_deref().is_some_and(|p| !Path::new(p).is_dir()) {
+            // a single file was given
+            return Ok(vec![location]);
+        }
+
+        let mut overrides = OverrideBuilder::new(&root);
+        if args.include.is_empty() {
+            overrides.add("*.md")?;
+        }
+        for glob in &args.include {
+            overrides.add(glob)?;
+        }
+        for glob in &args.exclude {
+            overrides.add(&format!("!{glob}"))?;
+        }
+        let walker = WalkBuilder::new(&root)
+            .overrides(overrides.build()?)
+            .require_git(false)
+            .sort_by_file_path(|a, b| a.cmp(b))
+            .build();
+
+        let mut locations = vec![];
+        for entry in walker {
+            let entry = entry?;
+            if entry.file_type().is_some_and(|t| t.is_file()) {
+                locations.push(Self {
+                    workspace_root: root.clone(),
+                    path_to_md: Some(entry.into_path()),
+                });
+            }
+        }
+        Ok(locations)
+    }
 }
 
 /// Tests of text parsing
@@ -69,7 +114,8 @@ impl JobLocation {
 mod tests {
     use crate::cli::args::Args;
     use crate::executor::job_location::JobLocation;
-    use std::env;
+    use std::{env, fs};
+    use uuid::Uuid;
 
     #[test]
     fn test_job_location_without_path() {
@@ -103,4 +149,50 @@ mod tests {
         assert!(location.path_to_md.unwrap().is_file());
         assert!(location.workspace_root.is_dir());
     }
+
+    #[test]
+    fn test_find_all_md_files() {
+        let root = env::temp_dir().join(format!("gem-{}", Uuid::new_v4()));
+        for dir in ["sub/deeper", "ignored", "other"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "README.md",
+            "sub/b.md",
+            "sub/deeper/c.md",
+            "sub/notes.txt",
+            "ignored/d.md",
+            "other/e.md",
+        ] {
+            fs::write(root.join(file), "# title\n").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "ignored/\n").unwrap();
+        let found = |exclude: Vec<String>| -> Vec<String> {
+            let args = Args {
+                recursive: true,
+                path: Some(root.to_string_lossy().to_string()),
+                exclude,
+                ..Args::default()
+            };
+            JobLocation::find_all(&args)
+                .unwrap()
+                .iter()
+                .map(|l| {
+                    let md = l.path_to_md.as_ref().unwrap();
+                    let md = md.strip_prefix(&l.workspace_root).unwrap();
+                    md.to_string_lossy().to_string()
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            found(vec![]),
+            vec!["README.md", "other/e.md", "sub/b.md", "sub/deeper/c.md"]
+        );
+        assert_eq!(
+            found(vec!["other".to_string(), "c.md".to_string()]),
+            vec!["README.md", "sub/b.md"]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
 }
@@ -0,0 +1,222 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// How the code chunks of a language run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "LanguageConfig")]
+pub struct Language {
+    /// the command tokens, the first one being the executable,
+    /// empty when the code chunks need a `cmd`
+    pub command: Vec<String>,
+    /// extension of the build files, without the dot
+    pub extension: Option<String>,
+    /// the command tokens compiling the build file before the command
+    /// runs, empty when the code chunks run without a build phase
+    pub build: Vec<String>,
+}
+
+/// a language in the configuration, either its command or a table
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LanguageConfig {
+    Command(Vec<String>),
+    Table {
+        #[serde(default)]
+        command: Vec<String>,
+        extension: Option<String>,
+        #[serde(default)]
+        build: Vec<String>,
+    },
+}
+
+impl From<LanguageConfig> for Language {
+    fn from(config: LanguageConfig) -> Self {
+        match config {
+            LanguageConfig::Command(command) => Self {
+                command,
+                extension: None,
+                build: vec![],
+            },
+            LanguageConfig::Table {
+                command,
+                extension,
+                build,
+            } => Self {
+                command,
+                extension,
+                build,
+            },
+        }
+    }
+}
+
+/// A mapping from code chunk languages to the command running them,
+/// used when the code chunk has no `cmd` attribute, to the
+/// extension of their build files and to the command compiling them.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Languages {
+    #[serde(flatten)]
+    map: HashMap<String, Language>,
+}
+
+impl Default for Languages {
+    fn default() -> Self {
+        let mut languages = Self {
+            map: HashMap::default(),
+        };
+        for lang in ["sh", "shell", "bash"] {
+            languages.set(lang, &["bash"], "sh");
+        }
+        languages.set("zsh", &["zsh"], "zsh");
+        languages.set("fish", &["fish"], "fish");
+        for lang in ["js", "javascript", "node"] {
+            languages.set(lang, &["node"], "js");
+        }
+        for lang in ["ts", "typescript"] {
+            languages.set(lang, &["ts-node"], "ts");
+        }
+        for lang in ["py", "python", "python3"] {
+            languages.set(lang, &["python3"], "py");
+        }
+        for lang in ["rb", "ruby"] {
+            languages.set(lang, &["ruby"], "rb");
+        }
+        for lang in ["pl", "perl"] {
+            languages.set(lang, &["perl"], "pl");
+        }
+        languages.set("php", &["php"], "php");
+        languages.set("lua", &["lua"], "lua");
+        languages.set("r", &["Rscript"], "R");
+        for lang in ["ps1", "powershell", "pwsh"] {
+            languages.set(lang, &["pwsh", "-File"], "ps1");
+        }
+        languages.set("go", &["go", "run"], "go");
+        // compiled, then the binary runs
+        for (lang, compiler, extension) in [
+            ("rust", "rustc", "rs"),
+            ("rs", "rustc", "rs"),
+            ("c", "cc", "c"),
+            ("cpp", "c++", "cpp"),
+        ] {
+            languages.set(lang, &[], extension);
+            languages.set_build(lang, &[compiler, "{file}", "-o", "{bin}"]);
+        }
+        // only the extension is known, the code chunks need a `cmd`
+        for (lang, extension) in [
+            ("java", "java"),
+            ("kotlin", "kt"),
+            ("swift", "swift"),
+            ("sql", "sql"),
+            ("toml", "toml"),
+            ("json", "json"),
+            ("yaml", "yaml"),
+        ] {
+            languages.set(lang, &[], extension);
+        }
+        languages
+    }
+}
+
+impl Languages {
+    pub fn set(&mut self, lang: &str, command: &[&str], extension: &str) {
+        self.map.insert(
+            lang.to_lowercase(),
+            Language {
+                command: command.iter().map(|t| t.to_string()).collect(),
+                extension: Some(extension.to_string()),
+                build: vec![],
+            },
+        );
+    }
+
+    /// set the command compiling the build files of a known language
+    pub fn set_build(&mut self, lang: &str, build: &[&str]) {
+        if let Some(language) = self.map.get_mut(&lang.to_lowercase()) {
+            language.build = build.iter().map(|t| t.to_string()).collect();
+        }
+    }
+
+    /// add the languages of the other table, or replace
+    /// the command and the extension they set
+    pub fn add_all(&mut self, other: &Languages) {
+        for (lang, language) in &other.map {
+            let current = self.map.entry(lang.to_lowercase()).or_default();
+            if !language.command.is_empty() {
+                current.command = language.command.clone();
+            }
+            if language.extension.is_some() {
+                current.extension = language.extension.clone();
+            }
+            if !language.build.is_empty() {
+                current.build = language.build.clone();
+            }
+        }
+    }
+
+    /// the command tokens of a language, if it's known
+    pub fn get(&self, lang: &str) -> Option<&[String]> {
+        self.map
+            .get(&lang.to_lowercase())
+            .filter(|language| !language.command.is_empty())
+            .map(|language| language.command.as_slice())
+    }
+
+    /// the command tokens compiling the build files of a language, if it has a build phase
+    pub fn build(&self, lang: &str) -> Option<&[String]> {
+        self.map
+            .get(&lang.to_lowercase())
+            .filter(|language| !language.build.is_empty())
+            .map(|language| language.build.as_slice())
+    }
+
+    /// the extension of the build files of a language, if it's known
+    pub fn extension(&self, lang: &str) -> Option<&str> {
+        self.map
+            .get(&lang.to_lowercase())
+            .and_then(|language| language.extension.as_deref())
+    }
+}
+
+#[test]
+fn test_default_languages() {
+    let languages = Languages::default();
+    assert_eq!(languages.get("sh"), Some(&["bash".to_string()][..]));
+    assert_eq!(languages.get("Python"), Some(&["python3".to_string()][..]));
+    assert_eq!(languages.get("cobol"), None);
+    assert_eq!(languages.get("rust"), None);
+    assert_eq!(languages.extension("rust"), Some("rs"));
+    assert_eq!(languages.extension("ts"), Some("ts"));
+    assert_eq!(
+        languages.build("c").map(|b| b.join(" ")),
+        Some("cc {file} -o {bin}".to_string())
+    );
+    assert_eq!(languages.build("py"), None);
+}
+
+#[test]
+fn test_deserialize_languages() {
+    #[derive(Deserialize)]
+    struct Config {
+        languages: Languages,
+    }
+    let toml = r#"
+    [languages]
+    py = ["python3", "-u"]
+    cobol = { command = ["cobc", "-xj"], extension = "cob" }
+    c = { build = ["gcc", "-O2", "{file}", "-o", "{bin}"] }
+    "#;
+    let conf = toml::from_str::<Config>(toml).unwrap();
+    let mut languages = Languages::default();
+    languages.add_all(&conf.languages);
+
+    assert_eq!(
+        languages.get("py"),
+        Some(&["python3".to_string(), "-u".to_string()][..])
+    );
+    assert_eq!(languages.extension("py"), Some("py"));
+    assert_eq!(languages.get("cobol").map(|c| c.len()), Some(2));
+    assert_eq!(languages.extension("cobol"), Some("cob"));
+    assert_eq!(languages.get("js"), Some(&["node".to_string()][..]));
+    assert_eq!(languages.build("c").map(|b| b.len()), Some(5));
+    assert_eq!(languages.extension("c"), Some("c"));
+}
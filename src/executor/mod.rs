@@ -1,9 +1,18 @@
 pub(crate) mod command_output;
+pub(crate) mod dependency_graph;
+pub(crate) mod env;
 pub(crate) mod executable;
 pub(crate) mod execution_plan;
+#[allow(clippy::module_inception)]
 mod executor;
+pub(crate) mod expected_output;
 pub(crate) mod failure;
 pub(crate) mod job;
 pub(crate) mod job_location;
+pub(crate) mod languages;
+pub(crate) mod params;
+pub(crate) mod process_group;
+pub(crate) mod session;
+pub(crate) mod tags;
 
-pub use {executor::Executor, executor::Task};
+pub use executor::{Executor, ExecutorOptions};
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+lazy_static! {
+    /// a `{{name}}` reference to a parameter in the code
+    static ref PARAM: Regex = Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_.-]*)\s*\}\}").unwrap();
+}
+
+/// a parameter declared in the front matter, without value when it's required
+pub type Declared = (String, Option<String>);
+
+/// read the `params` of the YAML front matter of a markdown text, e.g.
+///
+/// ```yaml
+/// ---
+/// params:
+///   version: 1.2.3
+///   token:
+/// ---
+/// ```
+///
+/// Only scalar values are supported, a parameter without value is required.
+pub fn front_matter_params(md: &str) -> Result<Vec<Declared>> {
+    let mut lines = md.lines().enumerate();
+    if lines.next().map(|(_, l)| l.trim_end()) != Some("---") {
+        return Ok(vec![]);
+    }
+    let mut params = vec![];
+    let mut in_params = false;
+    for (idx, line) in lines {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            return Ok(params);
+        }
+        if !line.starts_with([' ', '\t']) {
+            in_params = trimmed == "params:";
+            continue;
+        }
+        let entry = strip_comment(trimmed.trim());
+        if !in_params || entry.is_empty() {
+            continue;
+        }
+        let (name, value) = match entry.split_once(':') {
+            Some((name, value)) if !name.trim().is_empty() => (name.trim(), value.trim()),
+            _ => bail!(
+                "line {}: expected `name: value` in the params of the front matter",
+                idx + 1
+            ),
+        };
+        let value = match value {
+            "" | "~" | "null" => None,
+            value => Some(unquote(value).to_string()),
+        };
+        params.push((name.to_string(), value));
+    }
+    // no end of the front matter, it was a thematic break
+    Ok(vec![])
+}
+
+/// the values of the parameters: the `--param` ones, then the defaults of
+/// the front matter, then the ones of the configuration.
+///
+/// Fails when a required parameter has no value.
+pub fn resolve(
+    declared: &[Declared],
+    defaults: &BTreeMap<String, String>,
+    args: &[(String, String)],
+) -> Result<BTreeMap<String, String>> {
+    let mut values = defaults.clone();
+    let mut missing = vec![];
+    for (name, value) in declared {
+        match value {
+            Some(value) => {
+                values.insert(name.clone(), value.clone());
+            }
+            None if !values.contains_key(name) => missing.push(name.as_str()),
+            None => {}
+        }
+    }
+    missing.retain(|name| !args.iter().any(|(arg, _)| arg == name));
+    if !missing.is_empty() {
+        bail!(
+            "missing required parameter(s) `{}`, set them with `--param {}=<value>`",
+            missing.join("`, `"),
+            missing[0]
+        );
+    }
+    values.extend(args.iter().cloned());
+    Ok(values)
+}
+
+/// replace the `{{name}}` references to the parameters, keeping the unknown ones
+pub fn substitute(text: &str, params: &BTreeMap<String, String>) -> String {
+    PARAM
+        .replace_all(text, |captures: &Captures| match params.get(&captures[1]) {
+            Some(value) => value.clone(),
+            None => captures[0].to_string(),
+        })
+        .into_owned()
+}
+
+/// a YAML value without its trailing comment
+fn strip_comment(value: &str) -> &str {
+    if value.ends_with(['"', '\'']) {
+        return value;
+    }
+    match value.find(" #") {
+        Some(idx) => value[..idx].trim_end(),
+        None if value.starts_with('#') => "",
+        None => value,
+    }
+}
+
+/// a YAML scalar without its quotes
+fn unquote(value: &str) -> &str {
+    ['"', '\'']
+        .iter()
+        .find_map(|q| value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q)))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{front_matter_params, resolve, substitute};
+
+    #[test]
+    fn read_front_matter() {
+        let md = "---\ntitle: Release\nparams:\n  version: 1.2.3 # default\n  channel: \"stable\"\n  token:\nauthor: me\n---\n# Release\n";
+        let declared = front_matter_params(md).unwrap();
+        assert_eq!(
+            declared,
+            vec![
+                ("version".to_string(), Some("1.2.3".to_string())),
+                ("channel".to_string(), Some("stable".to_string())),
+                ("token".to_string(), None),
+            ]
+        );
+        assert!(front_matter_params("# no front matter\n---\n")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn resolve_params() {
+        let declared = vec![
+            ("version".to_string(), Some("1.2.3".to_string())),
+            ("token".to_string(), None),
+        ];
+        let defaults = BTreeMap::from([("registry".to_string(), "npm".to_string())]);
+        let error = resolve(&declared, &defaults, &[]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "missing required parameter(s) `token`, set them with `--param token=<value>`"
+        );
+
+        let args = vec![
+            ("token".to_string(), "t0k".to_string()),
+            ("version".to_string(), "2.0.0".to_string()),
+        ];
+        let params = resolve(&declared, &defaults, &args).unwrap();
+        assert_eq!(
+            substitute(
+                "npm publish {{ version }} --registry {{registry}} {{other}}",
+                &params
+            ),
+            "npm publish 2.0.0 --registry npm {{other}}"
+        );
+        assert_eq!(params["token"], "t0k");
+    }
+}
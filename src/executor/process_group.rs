@@ -0,0 +1,148 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// maximum number of process groups killed when markdown-gem is interrupted
+const SLOTS: usize = 256;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const FREE: AtomicI32 = AtomicI32::new(0);
+
+/// the ids of the live process groups, `0` in the free slots. They're
+/// atomics so that the signal handler can read them
+static GROUPS: [AtomicI32; SLOTS] = [FREE; SLOTS];
+
+/// A process group of a task or of a session, each code chunk running in
+/// its own so that its descendants are killed with it. It's killed when
+/// markdown-gem is interrupted, until it's dropped.
+pub struct ProcessGroup {
+    /// id of the process leading the group
+    pid: Option<u32>,
+    /// slot of the group in `GROUPS`, if there was a free one
+    slot: Option<usize>,
+}
+
+impl ProcessGroup {
+    /// track the group led by the process
+    pub fn new(pid: Option<u32>) -> Self {
+        let slot = pid.and_then(|pid| {
+            let slot = GROUPS.iter().position(|group| {
+                group
+                    .compare_exchange(0, pid as i32, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            });
+            if slot.is_none() {
+                debug!(
+                    "too many process groups, {} isn't killed on interruption",
+                    pid
+                );
+            }
+            slot
+        });
+        Self { pid, slot }
+    }
+
+    /// kill the processes of the group
+    pub fn kill(&self) {
+        kill(self.pid);
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            GROUPS[slot].store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// kill the process group led by the process, so that its descendants die with it
+#[cfg(unix)]
+fn kill(pid: Option<u32>) {
+    if let Some(pid) = pid {
+        // the task leads its own process group, see `Job::command_with`
+        // SAFETY: killpg has no memory safety requirement
+        if unsafe { libc::killpg(pid as libc::pid_t, libc::SIGKILL) } != 0 {
+            debug!("process group {} already gone", pid);
+        }
+    }
+}
+
+/// on other systems only the child, dropped with its task, is killed
+#[cfg(not(unix))]
+fn kill(_pid: Option<u32>) {}
+
+/// kill the live process groups when markdown-gem gets SIGINT or SIGTERM,
+/// as they're out of the foreground process group the terminal signals
+#[cfg(unix)]
+pub fn kill_on_signals() {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler only calls async-signal-safe functions
+        unsafe {
+            libc::signal(
+                signal,
+                on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn kill_on_signals() {}
+
+/// kill the live process groups, then die of the signal
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    for group in GROUPS.iter() {
+        let pid = group.load(Ordering::SeqCst);
+        if pid != 0 {
+            // SAFETY: killpg, signal and raise are async-signal-safe
+            unsafe {
+                libc::killpg(pid, libc::SIGKILL);
+            }
+        }
+    }
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::io::Read;
+    use std::process::{Command, Stdio};
+
+    use super::{ProcessGroup, GROUPS};
+
+    #[test]
+    fn track_and_kill_the_group() {
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "sleep 30 & wait"])
+            .stdout(Stdio::piped());
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut child = command.spawn().unwrap();
+        let pid = child.id();
+
+        let group = ProcessGroup::new(Some(pid));
+        let tracked = |pid: u32| {
+            GROUPS
+                .iter()
+                .any(|g| g.load(super::Ordering::SeqCst) == pid as i32)
+        };
+        assert!(tracked(pid));
+
+        group.kill();
+        child.wait().unwrap();
+        // the end of stdout once `sleep` is killed too
+        let mut stdout = String::new();
+        child
+            .stdout
+            .take()
+            .unwrap()
+            .read_to_string(&mut stdout)
+            .unwrap();
+
+        drop(group);
+        assert!(!tracked(pid));
+    }
+}
@@ -0,0 +1,442 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+
+use anyhow::{anyhow, bail, Result};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{Child, ChildStderr, ChildStdin, ChildStdout, Command},
+};
+use uuid::Uuid;
+
+use crate::executor::command_output::{
+    ChunkRef, CommandExecInfo, CommandOutputLine, CommandStream,
+};
+use crate::executor::executor::{LineSender, TaskOutput};
+use crate::executor::job::Job;
+use crate::executor::process_group::ProcessGroup;
+
+/// runs the files of the python code chunks in the same globals,
+/// then writes the marker with the status on stdout and on stderr
+static PYTHON_DRIVER: &str = r#"
+import sys, traceback
+scope = {"__name__": "__main__"}
+for request in sys.stdin:
+    path, marker = request.rstrip("\n").split("\t")
+    status = 0
+    try:
+        with open(path) as f:
+            exec(compile(f.read(), path, "exec"), scope)
+    except SystemExit as e:
+        status = e.code if isinstance(e.code, int) else int(e.code is not None)
+    except BaseException as e:
+        # without the frame of the driver
+        traceback.print_exception(type(e), e, e.__traceback__.tb_next)
+        status = 1
+    sys.stderr.flush()
+    print(marker, status, flush=True)
+    print(marker, file=sys.stderr, flush=True)
+"#;
+
+/// runs the files of the node code chunks in the same global context,
+/// then writes the marker with the status on stdout and on stderr
+static NODE_DRIVER: &str = r#"
+const fs = require("fs"), vm = require("vm"), readline = require("readline");
+globalThis.require = require;
+readline.createInterface({ input: process.stdin }).on("line", (request) => {
+    const [path, marker] = request.split("\t");
+    let status = 0;
+    try {
+        vm.runInThisContext(fs.readFileSync(path, "utf8"), { filename: path });
+    } catch (e) {
+        console.error(e && e.stack ? e.stack : String(e));
+        status = 1;
+    }
+    console.log(marker + " " + status);
+    console.error(marker);
+});
+"#;
+
+/// The interpreters a session can run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpreter {
+    Bash,
+    Python,
+    Node,
+}
+
+impl Interpreter {
+    /// the interpreter of the code chunks of a language, if it has one
+    pub fn for_lang(lang: &str) -> Option<Self> {
+        match lang.to_lowercase().as_str() {
+            "sh" | "shell" | "bash" => Some(Self::Bash),
+            "py" | "python" | "python3" => Some(Self::Python),
+            "js" | "javascript" | "node" => Some(Self::Node),
+            _ => None,
+        }
+    }
+
+    /// the command tokens starting the interpreter, reading the requests on stdin
+    fn command(self) -> Vec<String> {
+        let tokens: &[&str] = match self {
+            Self::Bash => &["bash"],
+            Self::Python => &["python3", "-u", "-c", PYTHON_DRIVER],
+            Self::Node => &["node", "-e", NODE_DRIVER],
+        };
+        tokens.iter().map(|t| t.to_string()).collect()
+    }
+
+    /// the line asking the interpreter to run a file and to write the marker after it
+    fn request(self, file: &Path, marker: &str) -> String {
+        let file = file.to_string_lossy();
+        match self {
+            Self::Bash => format!(
+                ". '{}'; printf '%s %s\\n' {marker} \"$?\"; printf '%s\\n' {marker} >&2\n",
+                file.replace('\'', r"'\''")
+            ),
+            Self::Python | Self::Node => format!("{file}\t{marker}\n"),
+        }
+    }
+}
+
+impl Display for Interpreter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Bash => "bash",
+            Self::Python => "python",
+            Self::Node => "node",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A long-lived interpreter running the code chunks of a `session` one
+/// after the other, so that they share their state. Each code chunk is
+/// followed by a marker on stdout and stderr which ends its output, the
+/// one on stdout telling its exit status.
+///
+/// The interpreter is killed, with the processes it started, when
+/// the session is dropped at the end of the run.
+pub struct Session {
+    name: String,
+    interpreter: Interpreter,
+    child: Child,
+    /// the process group led by the interpreter
+    group: ProcessGroup,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    stderr: Lines<BufReader<ChildStderr>>,
+    /// whether the interpreter exited or was killed
+    ended: bool,
+}
+
+impl Session {
+    /// start the interpreter of a session, with the environment
+    /// and in the working directory of its first job
+    pub fn start(name: &str, interpreter: Interpreter, job: &Job) -> Result<Self> {
+        let mut command = Command::from(job.command_with(&interpreter.command(), false)?);
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let mut child = command
+            .spawn()
+            .map_err(|e| anyhow!("failed to start the {interpreter} session `{name}`: {e}"))?;
+        let pipes = (child.stdin.take(), child.stdout.take(), child.stderr.take());
+        let (Some(stdin), Some(stdout), Some(stderr)) = pipes else {
+            bail!("session `{name}` missing its pipes");
+        };
+        Ok(Self {
+            name: name.to_string(),
+            interpreter,
+            group: ProcessGroup::new(child.id()),
+            child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+            stderr: BufReader::new(stderr).lines(),
+            ended: false,
+        })
+    }
+
+    /// run the file of a code chunk in the session, sending its output
+    /// lines until the markers, or until the interpreter exits
+    pub async fn run(
+        &mut self,
+        file: &Path,
+        with_stdout: bool,
+        chunk: &ChunkRef,
+        line_sender: &LineSender,
+    ) -> Result<TaskOutput> {
+        if self.ended {
+            bail!("the session `{}` ended before this code chunk", self.name);
+        }
+        let marker = format!("__gem_session_{}__", Uuid::new_v4().simple());
+        let request = self.interpreter.request(file, &marker);
+        if let Err(e) = self.stdin.write_all(request.as_bytes()).await {
+            self.ended = true;
+            bail!("the session `{}` ended: {}", self.name, e);
+        }
+        self.stdin.flush().await?;
+        let stdout = read_until(
+            &mut self.stdout,
+            &marker,
+            with_stdout.then_some(CommandStream::StdOut),
+            chunk,
+            line_sender,
+        );
+        let stderr = read_until(
+            &mut self.stderr,
+            &marker,
+            Some(CommandStream::StdErr),
+            chunk,
+            line_sender,
+        );
+        let ((stdout, end), (stderr, _)) = tokio::try_join!(stdout, stderr)?;
+        let status = match end {
+            Some(end) => end.trim().parse().ok().map(exit_status),
+            None => {
+                // the code chunk exited the interpreter, its status is the chunk's one
+                self.ended = true;
+                self.child.wait().await.ok()
+            }
+        };
+        Ok(TaskOutput {
+            status,
+            stdout: if with_stdout { stdout } else { vec![] },
+            stderr_lines: stderr.len(),
+        })
+    }
+
+    /// kill the interpreter and the processes it started
+    pub fn kill(&mut self) {
+        if !self.ended {
+            self.ended = true;
+            self.group.kill();
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// read the lines of a stream until the marker, sending them when they have
+/// an origin. Returns the lines with what follows the marker, nothing when
+/// the stream ended before it.
+async fn read_until(
+    lines: &mut Lines<BufReader<impl tokio::io::AsyncRead + Unpin>>,
+    marker: &str,
+    origin: Option<CommandStream>,
+    chunk: &ChunkRef,
+    line_sender: &LineSender,
+) -> Result<(Vec<String>, Option<String>)> {
+    let mut captured = vec![];
+    while let Some(line) = lines.next_line().await? {
+        // an output without final newline is followed by the marker
+        let (line, end) = match line.split_once(marker) {
+            Some(("", after)) => return Ok((captured, Some(after.to_string()))),
+            Some((before, after)) => (before.to_string(), Some(after.to_string())),
+            None => (line, None),
+        };
+        if let Some(origin) = origin {
+            let response = CommandExecInfo::Line {
+                chunk: chunk.clone(),
+                line: CommandOutputLine {
+                    content: line.clone(),
+                    origin,
+                },
+            };
+            if line_sender.send(response).is_err() {
+                return Err(anyhow!("channel closed"));
+            }
+        }
+        captured.push(line);
+        if end.is_some() {
+            return Ok((captured, end));
+        }
+    }
+    Ok((captured, None))
+}
+
+/// the exit status written after the marker
+#[cfg(unix)]
+fn exit_status(code: i32) -> ExitStatus {
+    std::os::unix::process::ExitStatusExt::from_raw((code & 0xff) << 8)
+}
+
+#[cfg(windows)]
+fn exit_status(code: i32) -> ExitStatus {
+    std::os::windows::process::ExitStatusExt::from_raw(code as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use uuid::Uuid;
+
+    use super::{exit_status, read_until, Interpreter, Session};
+    use crate::executor::command_output::{ChunkRef, CommandStream};
+    use crate::executor::executable::{Executable, ExecutablePosition};
+    use crate::executor::executor::TaskOutput;
+    use crate::executor::job::Job;
+    use crate::executor::job_location::JobLocation;
+    use crate::executor::ExecutorOptions;
+    use crate::fenced_attributes::{Attributes, CodeChunk};
+
+    fn chunk() -> ChunkRef {
+        ChunkRef {
+            id: "s".to_string(),
+            md_file: PathBuf::from("README.md"),
+            position: ExecutablePosition::new(0, 2),
+            generated_id: false,
+        }
+    }
+
+    /// run the codes one after the other in a new session of the interpreter
+    async fn run_session(interpreter: Interpreter, lang: &str, codes: &[&str]) -> Vec<TaskOutput> {
+        let workspace_root = std::env::temp_dir().join(format!("gem-{}", Uuid::new_v4()));
+        fs::create_dir_all(&workspace_root).unwrap();
+        let location = JobLocation {
+            path_to_md: Some(workspace_root.join("README.md")),
+            workspace_root: workspace_root.clone(),
+        };
+        let (line_sender, _line_receiver) = crossbeam::channel::unbounded();
+        let mut session = None;
+        let mut outputs = vec![];
+        for code in codes {
+            let code_chunk = CodeChunk {
+                lang: Some(lang.to_string()),
+                attributes: Attributes::parse("{session=s}").unwrap(),
+                error: None,
+            };
+            let executable = Executable::new(
+                ExecutablePosition::new(0, 2),
+                Some(code_chunk),
+                code.to_string(),
+            );
+            let mut job = Job::new(&location, &executable, &ExecutorOptions::default()).unwrap();
+            let file = job.write_file().unwrap();
+            let session =
+                session.get_or_insert_with(|| Session::start("s", interpreter, &job).unwrap());
+            outputs.push(
+                session
+                    .run(&file, true, &chunk(), &line_sender)
+                    .await
+                    .unwrap(),
+            );
+        }
+        fs::remove_dir_all(workspace_root).unwrap();
+        outputs
+    }
+
+    fn code(output: &TaskOutput) -> Option<i32> {
+        output.status.and_then(|s| s.code())
+    }
+
+    #[test]
+    fn test_interpreter_of_languages() {
+        assert_eq!(Interpreter::for_lang("sh"), Some(Interpreter::Bash));
+        assert_eq!(Interpreter::for_lang("Python"), Some(Interpreter::Python));
+        assert_eq!(Interpreter::for_lang("js"), Some(Interpreter::Node));
+        assert_eq!(Interpreter::for_lang("rust"), None);
+        assert_eq!(
+            Interpreter::Bash.request(Path::new("/tmp/it's.sh"), "M"),
+            ". '/tmp/it'\\''s.sh'; printf '%s %s\\n' M \"$?\"; printf '%s\\n' M >&2\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn split_the_output_at_the_marker() {
+        let (line_sender, line_receiver) = crossbeam::channel::unbounded();
+        let stream: &[u8] = b"one\ntwoM 3\nnext\nM 0\nrest\n";
+        let mut lines = BufReader::new(stream).lines();
+
+        let (captured, end) = read_until(
+            &mut lines,
+            "M",
+            Some(CommandStream::StdOut),
+            &chunk(),
+            &line_sender,
+        )
+        .await
+        .unwrap();
+        assert_eq!(captured, vec!["one", "two"]);
+        assert_eq!(end.as_deref(), Some(" 3"));
+        assert_eq!(line_receiver.try_iter().count(), 2);
+
+        let (captured, end) = read_until(&mut lines, "M", None, &chunk(), &line_sender)
+            .await
+            .unwrap();
+        assert_eq!(captured, vec!["next"]);
+        assert_eq!(end.as_deref(), Some(" 0"));
+        assert_eq!(line_receiver.try_iter().count(), 0);
+
+        let (captured, end) = read_until(&mut lines, "M", None, &chunk(), &line_sender)
+            .await
+            .unwrap();
+        assert_eq!(captured, vec!["rest"]);
+        assert_eq!(end, None);
+    }
+
+    #[test]
+    fn exit_status_after_the_marker() {
+        assert!(exit_status(0).success());
+        assert_eq!(exit_status(3).code(), Some(3));
+        assert_eq!(exit_status(256 + 4).code(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn bash_session() {
+        let outputs = run_session(
+            Interpreter::Bash,
+            "bash",
+            &[
+                "X=2\necho x=$X\n",
+                "echo $((X * 2))\necho oops >&2\nfalse\n",
+                "printf 'no newline'\n",
+                "exit 3\n",
+            ],
+        )
+        .await;
+
+        assert_eq!(outputs[0].stdout, vec!["x=2"]);
+        assert_eq!(code(&outputs[0]), Some(0));
+        assert_eq!(outputs[1].stdout, vec!["4"]);
+        assert_eq!(outputs[1].stderr_lines, 1);
+        assert_eq!(code(&outputs[1]), Some(1));
+        assert_eq!(outputs[2].stdout, vec!["no newline"]);
+        assert_eq!(code(&outputs[2]), Some(0));
+        // exiting the interpreter ends the session with its status
+        assert_eq!(code(&outputs[3]), Some(3));
+    }
+
+    #[tokio::test]
+    async fn python_session() {
+        let outputs = run_session(
+            Interpreter::Python,
+            "python",
+            &[
+                "x = 2\nprint(x)\n",
+                "print(x * 2)\nraise ValueError('bad')\n",
+                "import sys\nsys.exit(4)\n",
+                "print(x + 1)\n",
+            ],
+        )
+        .await;
+
+        assert_eq!(outputs[0].stdout, vec!["2"]);
+        assert_eq!(code(&outputs[0]), Some(0));
+        assert_eq!(outputs[1].stdout, vec!["4"]);
+        assert!(outputs[1].stderr_lines > 0);
+        assert_eq!(code(&outputs[1]), Some(1));
+        // the driver catches `SystemExit`, the session goes on
+        assert_eq!(code(&outputs[2]), Some(4));
+        assert_eq!(outputs[3].stdout, vec!["3"]);
+    }
+}
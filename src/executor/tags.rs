@@ -0,0 +1,227 @@
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
+
+/// a boolean expression on the tags of a code chunk, e.g. `network and not slow`
+///
+/// `,` is a shorter `or`, `not` binds tighter than `and`, which binds
+/// tighter than `or`, and parentheses group the sub-expressions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagExpr {
+    Tag(String),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+impl TagExpr {
+    pub fn parse(text: &str) -> Result<Self> {
+        tokenize(text)
+            .and_then(Self::parse_tokens)
+            .with_context(|| format!("invalid tag expression `{text}`"))
+    }
+
+    fn parse_tokens(tokens: Vec<String>) -> Result<Self> {
+        let mut parser = Parser { tokens, idx: 0 };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => bail!("unexpected `{token}`"),
+        }
+    }
+
+    /// whether the tags of a code chunk match the expression
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match self {
+            Self::Tag(tag) => tags.contains(tag),
+            Self::Not(expr) => !expr.matches(tags),
+            Self::And(left, right) => left.matches(tags) && right.matches(tags),
+            Self::Or(left, right) => left.matches(tags) || right.matches(tags),
+        }
+    }
+}
+
+impl fmt::Display for TagExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tag(tag) => write!(f, "{tag}"),
+            Self::Not(expr) => match expr.as_ref() {
+                Self::Tag(_) | Self::Not(_) => write!(f, "not {expr}"),
+                _ => write!(f, "not ({expr})"),
+            },
+            Self::And(left, right) => {
+                for (idx, expr) in [left, right].into_iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, " and ")?;
+                    }
+                    match expr.as_ref() {
+                        Self::Or(..) => write!(f, "({expr})")?,
+                        _ => write!(f, "{expr}")?,
+                    }
+                }
+                Ok(())
+            }
+            Self::Or(left, right) => write!(f, "{left} or {right}"),
+        }
+    }
+}
+
+/// the selection of the code chunks by their tags, from `--tags` and `--skip-tags`
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    /// the expression the tags of the code chunks to run must match
+    pub tags: Option<TagExpr>,
+    /// the expression the tags of the code chunks to skip match
+    pub skip_tags: Option<TagExpr>,
+}
+
+impl TagFilter {
+    pub fn new(tags: Option<&str>, skip_tags: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            tags: tags.map(TagExpr::parse).transpose()?,
+            skip_tags: skip_tags.map(TagExpr::parse).transpose()?,
+        })
+    }
+
+    /// the reason to skip a code chunk with the given tags, if the filter rejects it
+    pub fn skip_reason(&self, tags: &[String]) -> Option<String> {
+        match (&self.tags, &self.skip_tags) {
+            (Some(expr), _) if !expr.matches(tags) => {
+                Some(format!("tags {tags:?} don't match --tags `{expr}`"))
+            }
+            (_, Some(expr)) if expr.matches(tags) => {
+                Some(format!("tags {tags:?} match --skip-tags `{expr}`"))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// split the expression into tags, keywords, commas and parentheses
+fn tokenize(text: &str) -> Result<Vec<String>> {
+    let mut tokens = vec![];
+    let mut word = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':') {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            tokens.push(std::mem::take(&mut word));
+        }
+        match c {
+            '(' | ')' | ',' => tokens.push(c.to_string()),
+            c if c.is_whitespace() => {}
+            c => bail!("invalid character `{c}`"),
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    Ok(tokens)
+}
+
+/// a recursive descent parser of the tokens of a tag expression
+struct Parser {
+    tokens: Vec<String>,
+    idx: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.idx).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<&str> {
+        match self.tokens.get(self.idx) {
+            Some(token) => {
+                self.idx += 1;
+                Ok(token)
+            }
+            None => bail!("unexpected end"),
+        }
+    }
+
+    fn or(&mut self) -> Result<TagExpr> {
+        let mut expr = self.and()?;
+        while matches!(self.peek(), Some("or" | ",")) {
+            self.idx += 1;
+            expr = TagExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<TagExpr> {
+        let mut expr = self.not()?;
+        while self.peek() == Some("and") {
+            self.idx += 1;
+            expr = TagExpr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<TagExpr> {
+        if self.peek() == Some("not") {
+            self.idx += 1;
+            return Ok(TagExpr::Not(Box::new(self.not()?)));
+        }
+        match self.next()? {
+            "(" => {
+                let expr = self.or()?;
+                match self.next()? {
+                    ")" => Ok(expr),
+                    token => bail!("expected `)`, got `{token}`"),
+                }
+            }
+            token @ (")" | "," | "and" | "or") => {
+                bail!("expected a tag, got `{token}`")
+            }
+            tag => Ok(TagExpr::Tag(tag.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TagExpr, TagFilter};
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_and_match_tag_expressions() {
+        let expr = TagExpr::parse("network and not slow").unwrap();
+        assert!(expr.matches(&tags(&["network"])));
+        assert!(!expr.matches(&tags(&["network", "slow"])));
+        assert!(!expr.matches(&tags(&[])));
+
+        let expr = TagExpr::parse("not (slow, destructive) and db-1").unwrap();
+        assert_eq!(expr.to_string(), "not (slow or destructive) and db-1");
+        assert!(expr.matches(&tags(&["db-1"])));
+        assert!(!expr.matches(&tags(&["db-1", "destructive"])));
+
+        let expr = TagExpr::parse("a or b and c").unwrap();
+        assert!(expr.matches(&tags(&["a"])));
+        assert!(!expr.matches(&tags(&["b"])));
+
+        for invalid in ["", "a and", "(a", "a b", "not", "a & b", "or a"] {
+            assert!(TagExpr::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn skip_reason_of_tag_filter() {
+        let filter = TagFilter::new(Some("network"), Some("slow")).unwrap();
+        assert_eq!(filter.skip_reason(&tags(&["network"])), None);
+        assert_eq!(
+            filter.skip_reason(&tags(&["local"])),
+            Some(r#"tags ["local"] don't match --tags `network`"#.to_string())
+        );
+        assert_eq!(
+            filter.skip_reason(&tags(&["network", "slow"])),
+            Some(r#"tags ["network", "slow"] match --skip-tags `slow`"#.to_string())
+        );
+        assert_eq!(TagFilter::default().skip_reason(&tags(&[])), None);
+    }
+}
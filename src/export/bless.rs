@@ -0,0 +1,192 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::executor::command_output::CommandStream;
+use crate::executor::executable::ExecutablePosition;
+use crate::fenced_attributes::code_chunk::CODE_FENCED_CHUNK;
+use crate::fenced_attributes::OutputMatch;
+use crate::report::RunReport;
+
+/// Rewrite the expected output blocks of the markdown file which don't
+/// match the output captured during the run.
+///
+/// Only the `exact` and `trimmed` blocks are rewritten, as a pattern can't
+/// be derived from the output. The rewritten blocks are marked as passed
+/// in the report. Returns the number of rewritten blocks.
+pub fn bless_file(md_path: &Path, report: &mut RunReport) -> Result<usize> {
+    let mut updates = vec![];
+    for chunk in report
+        .chunks
+        .iter_mut()
+        .filter(|c| c.chunk.md_file == md_path)
+    {
+        let stdout: Vec<String> = chunk
+            .output
+            .content(CommandStream::StdOut)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        for assertion in chunk.assertions.iter_mut().filter(|a| a.diff.is_some()) {
+            match assertion.matching {
+                OutputMatch::Exact | OutputMatch::Trimmed => {
+                    updates.push((assertion.expected.clone(), stdout.clone()));
+                    assertion.diff = None;
+                }
+                OutputMatch::Regex | OutputMatch::Contains => {
+                    warn!(
+                        "the {:?} output block at line {} can't be blessed",
+                        assertion.matching,
+                        assertion.expected.start + 1
+                    );
+                }
+            }
+        }
+    }
+    if updates.is_empty() {
+        return Ok(0);
+    }
+    let content = fs::read_to_string(md_path)?;
+    fs::write(md_path, replace_blocks(&content, &updates)?)?;
+    Ok(updates.len())
+}
+
+/// replace the lines between the fences of the blocks at the given
+/// positions, leaving every other byte of the content untouched
+fn replace_blocks(content: &str, updates: &[(ExecutablePosition, Vec<String>)]) -> Result<String> {
+    let mut lines: Vec<String> = content.split_inclusive('\n').map(String::from).collect();
+    let mut updates: Vec<&(ExecutablePosition, Vec<String>)> = updates.iter().collect();
+    // replace from the bottom so that the positions of the other blocks stay valid
+    updates.sort_by_key(|(position, _)| Reverse(position.start));
+    for (position, new_lines) in updates {
+        let is_fence = |idx: usize| {
+            lines
+                .get(idx)
+                .is_some_and(|l| CODE_FENCED_CHUNK.is_match(l.trim_end()))
+        };
+        if !is_fence(position.start) || !is_fence(position.end) {
+            bail!(
+                "no output block at lines {}-{}, was the file modified during the run?",
+                position.start + 1,
+                position.end + 1
+            );
+        }
+        let ending = if lines[position.start].ends_with("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let replacement = new_lines.iter().map(|l| format!("{l}{ending}"));
+        lines.splice(position.start + 1..position.end, replacement);
+    }
+    Ok(lines.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use crate::executor::command_output::{
+        ChunkRef, CommandExecInfo, CommandOutputLine, CommandStream,
+    };
+    use crate::executor::executable::ExecutablePosition;
+    use crate::export::bless::{bless_file, replace_blocks};
+    use crate::fenced_attributes::OutputMatch;
+    use crate::report::RunReport;
+
+    #[test]
+    fn replace_only_the_blocks_content() {
+        let content =
+            "# Title  \r\n```sh {id=a}\r\necho a\r\n```\r\n```output {for=a}\r\nb\r\n```\r\ntail";
+        let updates = vec![(ExecutablePosition::new(4, 6), vec!["a".to_string()])];
+        assert_eq!(
+            replace_blocks(content, &updates).unwrap(),
+            "# Title  \r\n```sh {id=a}\r\necho a\r\n```\r\n```output {for=a}\r\na\r\n```\r\ntail"
+        );
+    }
+
+    #[test]
+    fn replace_several_blocks() {
+        let content = "```output\nx\n```\ntext\n```output\n```\n";
+        let updates = vec![
+            (ExecutablePosition::new(0, 2), vec![]),
+            (
+                ExecutablePosition::new(4, 5),
+                vec!["1".to_string(), "2".to_string()],
+            ),
+        ];
+        assert_eq!(
+            replace_blocks(content, &updates).unwrap(),
+            "```output\n```\ntext\n```output\n1\n2\n```\n"
+        );
+    }
+
+    #[test]
+    fn bless_with_the_last_attempt() {
+        let md_path = std::env::temp_dir().join(format!("gem-bless-{}.md", std::process::id()));
+        fs::write(
+            &md_path,
+            "```sh {id=a, retries=1}\n./flaky\n```\n```output {for=a}\nok\n```\n",
+        )
+        .unwrap();
+        let chunk = ChunkRef {
+            id: "a".to_string(),
+            md_file: md_path.clone(),
+            position: ExecutablePosition::new(0, 2),
+            generated_id: false,
+        };
+        let line = |content: &str| CommandExecInfo::Line {
+            chunk: chunk.clone(),
+            line: CommandOutputLine {
+                content: content.to_string(),
+                origin: CommandStream::StdOut,
+            },
+        };
+        let mut report = RunReport::default();
+        for info in [
+            CommandExecInfo::Start {
+                chunk: chunk.clone(),
+            },
+            line("connection refused"),
+            CommandExecInfo::Retry {
+                chunk: chunk.clone(),
+                attempt: 1,
+                reason: "exit status: 1".to_string(),
+                delay: Duration::ZERO,
+            },
+            line("connected"),
+            CommandExecInfo::End {
+                chunk: chunk.clone(),
+                status: None,
+                passed: true,
+                duration: Duration::ZERO,
+            },
+            CommandExecInfo::Assertion {
+                chunk: chunk.clone(),
+                expected: ExecutablePosition::new(3, 5),
+                matching: OutputMatch::Exact,
+                passed: false,
+                diff: Some("-ok\n+connected\n".to_string()),
+            },
+        ] {
+            report.record(&info);
+        }
+
+        assert_eq!(bless_file(&md_path, &mut report).unwrap(), 1);
+        let blessed = fs::read_to_string(&md_path).unwrap();
+        fs::remove_file(&md_path).unwrap();
+        assert_eq!(
+            blessed,
+            "```sh {id=a, retries=1}\n./flaky\n```\n```output {for=a}\nconnected\n```\n"
+        );
+    }
+
+    #[test]
+    fn refuse_to_replace_outside_a_block() {
+        let updates = vec![(ExecutablePosition::new(0, 1), vec![])];
+        assert!(replace_blocks("text\n```\n", &updates).is_err());
+    }
+}
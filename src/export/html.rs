@@ -0,0 +1,220 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use pulldown_cmark::{html, Options, Parser};
+
+use crate::executor::command_output::CommandStream;
+use crate::export::split_after_chunks;
+use crate::report::{timed_out, ChunkOutcome, ChunkReport, RunReport};
+
+static STYLE: &str = r#"
+body { font-family: sans-serif; max-width: 60em; margin: 2em auto; padding: 0 1em; color: #222; }
+pre { background: #f6f8fa; padding: .8em; overflow-x: auto; border-radius: 4px; }
+table { border-collapse: collapse; }
+td, th { border: 1px solid #ccc; padding: .3em .6em; }
+.summary { padding: .6em; border-radius: 4px; background: #eef; }
+.chunk { border-left: 4px solid #999; margin: 0 0 1.5em; padding-left: .8em; }
+.chunk.passed { border-color: #2a2; }
+.chunk.failed { border-color: #d22; }
+.chunk.skipped { border-color: #aaa; color: #777; }
+.chunk .status { font-size: .9em; font-weight: bold; margin: .3em 0; }
+.chunk pre.output { background: #1e1e1e; color: #ddd; }
+.chunk .stderr { color: #f77; }
+.chunk pre.diff { background: #fff5f5; }
+"#;
+
+/// Export the markdown files, with the output captured during the run,
+/// as a self-contained HTML file
+pub fn write_file(md_paths: &[PathBuf], output_path: &Path, report: &RunReport) -> Result<()> {
+    let mut documents = Vec::with_capacity(md_paths.len());
+    for md_path in md_paths {
+        documents.push((md_path.as_path(), fs::read_to_string(md_path)?));
+    }
+    fs::write(output_path, render(&documents, report)?)?;
+    Ok(())
+}
+
+/// Render the markdown documents as HTML, one after the other, each code
+/// chunk being followed by its outcome, duration and output, the stderr
+/// lines styled apart
+pub fn render(documents: &[(&Path, String)], report: &RunReport) -> Result<String> {
+    let title = match documents {
+        [(md_path, _)] => escape(&md_path.to_string_lossy()),
+        _ => "markdown-gem".to_string(),
+    };
+    let mut page = String::new();
+    writeln!(page, "<!DOCTYPE html>")?;
+    writeln!(page, r#"<html><head><meta charset="utf-8">"#)?;
+    writeln!(page, "<title>{title}</title>")?;
+    writeln!(page, "<style>{STYLE}</style>")?;
+    writeln!(page, "</head><body>")?;
+    for (md_path, content) in documents {
+        writeln!(page, "<article>")?;
+        write_summary(&mut page, md_path, report)?;
+        for (markdown, chunks) in split_after_chunks(content, md_path, report) {
+            html::push_html(&mut page, Parser::new_ext(&markdown, Options::all()));
+            for chunk in chunks {
+                write_chunk(&mut page, chunk)?;
+            }
+        }
+        writeln!(page, "</article>")?;
+    }
+    writeln!(page, "</body></html>")?;
+    Ok(page)
+}
+
+fn write_summary(page: &mut String, md_path: &Path, report: &RunReport) -> Result<()> {
+    let summary = report.summary(md_path);
+    writeln!(
+        page,
+        r#"<p class="summary">{}: {} code chunk(s) passed, {} failed, {} skipped</p>"#,
+        escape(&md_path.to_string_lossy()),
+        summary.passed,
+        summary.failed,
+        summary.skipped,
+    )?;
+    Ok(())
+}
+
+fn write_chunk(page: &mut String, chunk: &ChunkReport) -> Result<()> {
+    let (class, status) = match &chunk.outcome {
+        ChunkOutcome::Skipped(reason) => ("skipped", format!("skipped: {reason}")),
+        ChunkOutcome::Error(message) => ("failed", format!("error: {message}")),
+        ChunkOutcome::TimedOut(timeout) => ("failed", timed_out(timeout)),
+        ChunkOutcome::BuildFailed(_) => {
+            ("failed", chunk.outcome.failure_reason().unwrap_or_default())
+        }
+        ChunkOutcome::Finished {
+            status: Some(status),
+            ..
+        } => (
+            if chunk.is_failure() {
+                "failed"
+            } else {
+                "passed"
+            },
+            status.to_string(),
+        ),
+        ChunkOutcome::Finished { status: None, .. } => (
+            if chunk.is_failure() {
+                "failed"
+            } else {
+                "passed"
+            },
+            "no exit status".to_string(),
+        ),
+        ChunkOutcome::Running => ("failed", "interrupted".to_string()),
+    };
+    let status = if chunk.attempts > 1 {
+        format!("{status}, after {} attempts", chunk.attempts)
+    } else {
+        status
+    };
+    writeln!(page, r#"<div class="chunk {class}">"#)?;
+    writeln!(
+        page,
+        r#"<div class="status">{} &mdash; {} &mdash; {:.3}s</div>"#,
+        escape(&chunk.chunk.id),
+        escape(&status),
+        chunk.duration.as_secs_f64(),
+    )?;
+    if let Some((name, value)) = &chunk.captured {
+        writeln!(
+            page,
+            r#"<div class="status">captured {} = {}</div>"#,
+            escape(name),
+            escape(value)
+        )?;
+    }
+    if !chunk.output.lines.is_empty() {
+        write!(page, r#"<pre class="output">"#)?;
+        for line in &chunk.output.lines {
+            let stream = match line.origin {
+                CommandStream::StdOut => "stdout",
+                CommandStream::StdErr => "stderr",
+            };
+            writeln!(
+                page,
+                r#"<span class="{stream}">{}</span>"#,
+                escape(&line.content)
+            )?;
+        }
+        writeln!(page, "</pre>")?;
+    }
+    for assertion in chunk.failed_assertions() {
+        writeln!(
+            page,
+            r#"<div class="status">output doesn't match the block at line {}</div>"#,
+            assertion.expected.start + 1
+        )?;
+        writeln!(
+            page,
+            r#"<pre class="diff">{}</pre>"#,
+            escape(assertion.diff.as_deref().unwrap_or_default())
+        )?;
+    }
+    writeln!(page, "</div>")?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::executor::command_output::{
+        ChunkRef, CommandExecInfo, CommandOutputLine, CommandStream,
+    };
+    use crate::executor::executable::ExecutablePosition;
+    use crate::export::html::render;
+    use crate::report::RunReport;
+
+    #[test]
+    fn render_chunk_output() {
+        let chunk = ChunkRef {
+            id: "build".to_string(),
+            md_file: PathBuf::from("README.md"),
+            position: ExecutablePosition::new(1, 3),
+            generated_id: false,
+        };
+        let mut report = RunReport::default();
+        report.record(&CommandExecInfo::Start {
+            chunk: chunk.clone(),
+        });
+        report.record(&CommandExecInfo::Line {
+            chunk,
+            line: CommandOutputLine {
+                content: "warning: <unused>".to_string(),
+                origin: CommandStream::StdErr,
+            },
+        });
+        let html = render(
+            &[(
+                Path::new("README.md"),
+                "# Build\n```sh {id=build}\nmake\n```\ndone\n".to_string(),
+            )],
+            &report,
+        )
+        .unwrap();
+
+        assert!(html.contains("<h1>Build</h1>"));
+        assert!(html.contains(r#"<div class="chunk failed">"#));
+        assert!(html.contains(r#"<span class="stderr">warning: &lt;unused&gt;</span>"#));
+        assert!(html.contains("<p>done</p>"));
+    }
+}
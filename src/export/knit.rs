@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::export::split_after_chunks;
+use crate::report::{timed_out, ChunkOutcome, ChunkReport, RunReport};
+
+/// Knit the markdown file with the output captured during the run,
+/// and write the result to `output_path`
+pub fn write_file(
+    md_path: &Path,
+    output_path: &Path,
+    report: &RunReport,
+    with_status: bool,
+) -> Result<()> {
+    let content = fs::read_to_string(md_path)?;
+    fs::write(output_path, knit(&content, md_path, report, with_status))?;
+    Ok(())
+}
+
+/// Build the markdown where each executed code chunk is followed by a
+/// fenced block holding its output and, optionally, its exit status.
+/// The skipped code chunks are followed by the reason they were skipped.
+pub fn knit(content: &str, md_path: &Path, report: &RunReport, with_status: bool) -> String {
+    let mut knitted = String::with_capacity(content.len());
+    for (markdown, chunks) in split_after_chunks(content, md_path, report) {
+        knitted.push_str(&markdown);
+        for chunk in chunks {
+            knitted.push_str(&chunk_output(chunk, with_status));
+        }
+    }
+    knitted
+}
+
+/// the markdown to add after a code chunk
+fn chunk_output(chunk: &ChunkReport, with_status: bool) -> String {
+    let mut md = String::new();
+    if let ChunkOutcome::Skipped(reason) = &chunk.outcome {
+        md.push_str(&format!("> skipped: {reason}\n"));
+        return md;
+    }
+    if !chunk.output.lines.is_empty() {
+        let fence = fence_for(chunk.output.lines.iter().map(|l| l.content.as_str()));
+        md.push_str(&format!("{fence}\n"));
+        for line in &chunk.output.lines {
+            md.push_str(&format!("{}\n", line.content));
+        }
+        md.push_str(&format!("{fence}\n"));
+    }
+    match &chunk.outcome {
+        ChunkOutcome::Error(message) => md.push_str(&format!("> error: {message}\n")),
+        ChunkOutcome::TimedOut(timeout) => md.push_str(&format!("> {}\n", timed_out(timeout))),
+        ChunkOutcome::BuildFailed(_) => {
+            md.push_str(&format!(
+                "> {}\n",
+                chunk.outcome.failure_reason().unwrap_or_default()
+            ));
+        }
+        ChunkOutcome::Finished { status, .. } if with_status => match status {
+            Some(status) => md.push_str(&format!("> {status}\n")),
+            None => md.push_str("> no exit status\n"),
+        },
+        _ => {}
+    }
+    if with_status && chunk.attempts > 1 {
+        md.push_str(&format!("> {} attempts\n", chunk.attempts));
+    }
+    md
+}
+
+/// a backtick fence longer than any backtick fence in the lines
+fn fence_for<'a, I: Iterator<Item = &'a str>>(lines: I) -> String {
+    let longest = lines
+        .map(|l| l.trim_start().chars().take_while(|c| *c == '`').count())
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use crate::executor::command_output::{
+        ChunkRef, CommandExecInfo, CommandOutputLine, CommandStream,
+    };
+    use crate::executor::executable::ExecutablePosition;
+    use crate::export::knit::knit;
+    use crate::fenced_attributes::Visibility;
+    use crate::report::RunReport;
+
+    fn chunk(id: &str, start: usize, end: usize) -> ChunkRef {
+        ChunkRef {
+            id: id.to_string(),
+            md_file: PathBuf::from("README.md"),
+            position: ExecutablePosition::new(start, end),
+            generated_id: false,
+        }
+    }
+
+    #[test]
+    fn output_after_the_chunks() {
+        let content =
+            "# Title\n```sh {id=a}\necho '```'\n```\ntext\n```sh {id=b, sys=[windows]}\ndir\n```";
+        let mut report = RunReport::default();
+        report.record(&CommandExecInfo::Start {
+            chunk: chunk("a", 1, 3),
+        });
+        report.record(&CommandExecInfo::Line {
+            chunk: chunk("a", 1, 3),
+            line: CommandOutputLine {
+                content: "```".to_string(),
+                origin: CommandStream::StdOut,
+            },
+        });
+        report.record(&CommandExecInfo::End {
+            chunk: chunk("a", 1, 3),
+            status: None,
+            passed: false,
+            duration: Duration::ZERO,
+        });
+        report.record(&CommandExecInfo::Skipped {
+            chunk: chunk("b", 5, 7),
+            reason: "sys filter".to_string(),
+        });
+
+        assert_eq!(
+            knit(content, Path::new("README.md"), &report, false),
+            "# Title\n```sh {id=a}\necho '```'\n```\n````\n```\n````\ntext\n```sh {id=b, sys=[windows]}\ndir\n```\n> skipped: sys filter\n"
+        );
+        assert!(knit(content, Path::new("README.md"), &report, true)
+            .contains("````\n> no exit status\ntext"));
+    }
+
+    #[test]
+    fn hidden_chunks() {
+        let content = "```sh {include=false}\nmkdir tmp\n```\ntext\n```sh {echo=false}\nls\n```\n";
+        let mut report = RunReport::default();
+        report.hide(
+            Path::new("README.md"),
+            vec![
+                (ExecutablePosition::new(0, 2), Visibility::Nothing),
+                (ExecutablePosition::new(4, 6), Visibility::Output),
+            ],
+        );
+        for chunk in [chunk("", 0, 2), chunk("", 4, 6)] {
+            report.record(&CommandExecInfo::Line {
+                chunk: chunk.clone(),
+                line: CommandOutputLine {
+                    content: "out".to_string(),
+                    origin: CommandStream::StdOut,
+                },
+            });
+            report.record(&CommandExecInfo::End {
+                chunk,
+                status: None,
+                passed: true,
+                duration: Duration::ZERO,
+            });
+        }
+
+        assert_eq!(
+            knit(content, Path::new("README.md"), &report, false),
+            "text\n```\nout\n```\n"
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+use crate::fenced_attributes::Visibility;
+use crate::report::{ChunkReport, RunReport};
+
+pub(crate) mod bless;
+pub(crate) mod html;
+pub(crate) mod knit;
+
+/// Split the markdown content after each code chunk of the file found in
+/// the report. Returns the pieces of markdown, each with the reports of the
+/// code chunks it ends with, if any. The code chunks with `echo=false` are
+/// left out of the markdown, those with `include=false` have no report either.
+fn split_after_chunks<'r>(
+    content: &str,
+    md_path: &Path,
+    report: &'r RunReport,
+) -> Vec<(String, Vec<&'r ChunkReport>)> {
+    let chunks: Vec<&ChunkReport> = report
+        .chunks
+        .iter()
+        .filter(|c| c.chunk.md_file == md_path)
+        .collect();
+    let hidden: Vec<_> = report
+        .hidden
+        .iter()
+        .filter(|(p, ..)| p == md_path)
+        .collect();
+    let mut pieces = vec![];
+    let mut piece = String::new();
+    for (idx, line) in content.split_inclusive('\n').enumerate() {
+        let echo = !hidden
+            .iter()
+            .any(|(_, position, _)| (position.start..=position.end).contains(&idx));
+        if echo {
+            piece.push_str(line);
+        }
+        let ending: Vec<&ChunkReport> = chunks
+            .iter()
+            .filter(|c| c.chunk.position.end == idx)
+            .filter(|c| report.visibility(md_path, &c.chunk.position) != Visibility::Nothing)
+            .copied()
+            .collect();
+        if !ending.is_empty() {
+            if !piece.is_empty() && !piece.ends_with('\n') {
+                piece.push('\n');
+            }
+            pieces.push((std::mem::take(&mut piece), ending));
+        }
+    }
+    if !piece.is_empty() {
+        pieces.push((piece, vec![]));
+    }
+    pieces
+}
@@ -1,18 +1,69 @@
-use anyhow::Result;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
 use hocon::HoconLoader;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::executor::env::is_variable_name;
+
 fn serde_default_true() -> bool {
     true
 }
 
+/// How the captured output of a code chunk is compared with an expected output block
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMatch {
+    /// the output must be equal to the block
+    #[default]
+    Exact,
+    /// the output must be equal to the block, ignoring the surrounding whitespaces
+    Trimmed,
+    /// the output must match the block as a regular expression
+    Regex,
+    /// the output must contain the block
+    Contains,
+}
+
+/// The directory the relative `cwd` of a code chunk starts from, and where
+/// it runs without `cwd`
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkDir {
+    /// the workspace root
+    #[default]
+    Workspace,
+    /// the directory of the markdown file
+    Markdown,
+}
+
+/// What the view and the exports show of a code chunk
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Visibility {
+    /// the source and the output
+    #[default]
+    All,
+    /// only the output, with `echo=false`
+    Output,
+    /// nothing, with `include=false`
+    Nothing,
+}
+
 /// Code chunk attributes, can be used to defined filters, path, arguments and etc
-#[derive(Deserialize, Debug, Default, PartialEq, Eq, Clone)]
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Attributes {
-    /// optional id, to use for TODO:dependencies
+    /// optional id, to refer to the code chunk in `depends_on` and `for`
     pub id: Option<String>,
 
+    /// whether the id was generated, the code chunk having none
+    #[serde(skip)]
+    pub generated_id: bool,
+
+    /// ids of the code chunks which must succeed before this one runs
+    pub depends_on: Option<Vec<String>>,
+
     /// system filter, e.g `linux`, `macos`, `windows` and etc.
     /// derived from https://doc.rust-lang.org/std/env/consts/constant.OS.html
     pub sys: Option<Vec<String>>,
@@ -25,6 +76,10 @@ pub struct Attributes {
     /// derived from https://doc.rust-lang.org/std/env/consts/constant.ARCH.html
     pub arch: Option<Vec<String>>,
 
+    /// tags of the code chunk, e.g. `slow`, `network`, selecting the code
+    /// chunks to run with `--tags` and `--skip-tags`
+    pub tags: Option<Vec<String>>,
+
     /// code chunk command name or path, e.g. `sh`, `node` and etc.
     pub cmd: Option<String>,
 
@@ -34,68 +89,353 @@ pub struct Attributes {
     /// `PATH` env variable for the commands
     pub path: Option<String>,
 
-    /// determines if markdown-gem should execute the code chunk as a file, default `true`
+    /// directories added before the `PATH` of the commands
+    pub path_prepend: Option<Vec<String>>,
+
+    /// directories added after the `PATH` of the commands
+    pub path_append: Option<Vec<String>>,
+
+    /// env variables of the commands, their values can refer to `${VAR}`
+    pub env: Option<BTreeMap<String, String>>,
+
+    /// dotenv file with env variables of the commands, relative to the workspace
+    pub env_file: Option<String>,
+
+    /// working directory of the command, relative to the `workdir`
+    pub cwd: Option<String>,
+
+    /// whether the relative `cwd` starts from the `workspace` or from the `markdown` file
+    /// directory, default `--workdir`
+    pub workdir: Option<WorkDir>,
+
+    /// determines if markdown-gem should execute the code chunk as a file, default `true`,
+    /// otherwise the code goes after the `inline_flag` or to the command stdin
     #[serde(default = "serde_default_true")]
     pub as_file: bool,
 
+    /// flag of the command taking the code inline when `as_file=false`, e.g. `-c` or `-e`
+    pub inline_flag: Option<String>,
+
+    /// name of the build file, e.g. `main.go` or `Dockerfile`, by default the id
+    /// with the extension of the language
+    pub filename: Option<String>,
+
+    /// command compiling the build file before the code chunk runs, e.g.
+    /// `["gcc", "{file}", "-o", "{bin}"]`, by default the one of the language
+    pub build: Option<Vec<String>>,
+
+    /// name of the interpreter session shared with the other code chunks of
+    /// the same `session`, keeping the state of the previous ones
+    pub session: Option<String>,
+
+    /// name of the variable storing the trimmed stdout of the code chunk, given
+    /// to the next code chunks in their environment and as `${VAR}` in their code
+    pub capture: Option<String>,
+
+    /// regex of the value to capture in stdout, its first group if it has one
+    pub capture_regex: Option<String>,
+
     /// determines if markdown-gem should display stdout of the code chunk, default `true`
     #[serde(default = "serde_default_true")]
     pub stdout: bool,
 
-    /// determines if markdown-gem should allow warnings, default `true`
+    /// determines if markdown-gem should run the code chunk, default `true`,
+    /// otherwise it's only shown
+    #[serde(default = "serde_default_true")]
+    pub eval: bool,
+
+    /// determines if markdown-gem should show the source of the code chunk, default `true`
+    #[serde(default = "serde_default_true")]
+    pub echo: bool,
+
+    /// determines if markdown-gem should show the code chunk at all, default `true`,
+    /// otherwise it runs without showing its source or its output
+    #[serde(default = "serde_default_true")]
+    pub include: bool,
+
+    /// determines if markdown-gem should allow output on stderr, default `true`
     #[serde(default = "serde_default_true")]
     pub allow_warnings: bool,
 
-    /// determines if markdown-gem should TODO: allow errors, default `true`
+    /// determines if markdown-gem should allow a non-zero exit status, default `false`
     #[serde(default)]
     pub allow_errors: bool,
 
+    /// duration after which the code chunk is killed, e.g. `30s` or `5m`
+    pub timeout: Option<String>,
+
+    /// number of times a failing code chunk is run again, default `0`
+    #[serde(default)]
+    pub retries: usize,
+
+    /// duration to wait before running a failing code chunk again, e.g. `5s`
+    pub retry_delay: Option<String>,
+
+    /// lets markdown-gem run the code chunk alongside other `parallel` code chunks, see `--jobs`
+    #[serde(default)]
+    pub parallel: bool,
+
     /// tells markdown-gem to run the Code chunk in sudo
     #[serde(default)]
     pub with_sudo: bool,
+
+    /// id of the code chunk whose output is expected in an `output` block
+    #[serde(rename = "for")]
+    pub for_id: Option<String>,
+
+    /// how the `output` block is compared with the code chunk output, default `exact`
+    #[serde(rename = "match", default)]
+    pub matching: OutputMatch,
+}
+
+impl Default for Attributes {
+    /// the attributes of a code chunk without any, as parsed from `{}`
+    fn default() -> Self {
+        Self {
+            id: None,
+            generated_id: false,
+            depends_on: None,
+            sys: None,
+            linux_distro: None,
+            arch: None,
+            tags: None,
+            cmd: None,
+            args: None,
+            path: None,
+            path_prepend: None,
+            path_append: None,
+            env: None,
+            env_file: None,
+            cwd: None,
+            workdir: None,
+            as_file: true,
+            inline_flag: None,
+            filename: None,
+            build: None,
+            session: None,
+            capture: None,
+            capture_regex: None,
+            stdout: true,
+            eval: true,
+            echo: true,
+            include: true,
+            allow_warnings: true,
+            allow_errors: false,
+            timeout: None,
+            retries: 0,
+            retry_delay: None,
+            parallel: false,
+            with_sudo: false,
+            for_id: None,
+            matching: OutputMatch::default(),
+        }
+    }
 }
 
 impl Attributes {
+    /// what is shown of the code chunk, `include=false` hiding more than `echo=false`
+    pub fn visibility(&self) -> Visibility {
+        match (self.include, self.echo) {
+            (false, _) => Visibility::Nothing,
+            (true, false) => Visibility::Output,
+            (true, true) => Visibility::All,
+        }
+    }
+
     /// Parses block attributes  text e.g. {#identifier .class1 .class2 key1=value1 key2=value2}
     pub fn parse(text: &str) -> Result<Self> {
-        let hocon = HoconLoader::new().load_str(text)?.hocon()?;
+        Self::parse_with_defaults(text, "")
+    }
+
+    /// Parses block attributes text, on top of the default attributes
+    /// coming from the configuration, in HOCON as well
+    pub fn parse_with_defaults(text: &str, defaults: &str) -> Result<Self> {
+        let mut loader = HoconLoader::new();
+        if !defaults.is_empty() {
+            loader = loader.load_str(defaults)?;
+        }
+        let hocon = loader.load_str(text)?.hocon()?;
         let attributes: Attributes = hocon.resolve()?;
+        if let Some(name) = attributes.capture.as_ref().filter(|n| !is_variable_name(n)) {
+            bail!("`{name}` can't be captured, the variable names are made of letters, digits and `_`");
+        }
+        Ok(Self::with_generated_id(attributes))
+    }
+
+    /// the attributes with a generated id when they have none
+    pub fn with_generated_id(attributes: Self) -> Self {
         if attributes.id.is_some() {
-            Ok(attributes)
+            attributes
         } else {
-            Ok(Attributes {
+            Attributes {
                 id: Some(Uuid::new_v4().to_string()),
+                generated_id: true,
                 ..attributes
-            })
+            }
         }
     }
 
+    /// the attributes setting the environment and the working directory of
+    /// the process, with their values, shared by the code chunks of a session
+    pub fn process_settings(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("env", format!("{:?}", self.env)),
+            ("env_file", format!("{:?}", self.env_file)),
+            ("path", format!("{:?}", self.path)),
+            ("path_prepend", format!("{:?}", self.path_prepend)),
+            ("path_append", format!("{:?}", self.path_append)),
+            ("cwd", format!("{:?}", self.cwd)),
+            ("workdir", format!("{:?}", self.workdir)),
+        ]
+    }
+
+    /// the parsed `timeout` attribute
+    pub fn timeout(&self) -> Result<Option<Duration>> {
+        parse_duration("timeout", self.timeout.as_deref())
+    }
+
+    /// the parsed `retry_delay` attribute, no delay by default
+    pub fn retry_delay(&self) -> Result<Duration> {
+        Ok(parse_duration("retry_delay", self.retry_delay.as_deref())?.unwrap_or_default())
+    }
+
     #[allow(dead_code)]
     pub fn is_empty(&self) -> bool {
         self.cmd.is_none() && self.arch.is_none() && self.args.is_none() && self.sys.is_none()
     }
 }
 
+/// parse a duration attribute, e.g. `30s` or `1m 30s`
+fn parse_duration(name: &str, value: Option<&str>) -> Result<Option<Duration>> {
+    value
+        .map(|v| humantime::parse_duration(v).map_err(|e| anyhow!("invalid {name} `{v}`: {e}")))
+        .transpose()
+}
+
 #[test]
 fn parse_attributes_test() {
     let attributes = Attributes::parse("");
-    assert_eq!(attributes.is_ok(), true);
+    assert!(attributes.is_ok());
 
     let attributes = attributes.unwrap();
-    assert_eq!(attributes.id.is_some(), true);
-    assert_eq!(attributes.is_empty(), true);
+    assert!(attributes.id.is_some());
+    assert!(attributes.is_empty());
 
     let attributes = Attributes::parse("{id: test, sys=[macos], args=[test1, test2]}}");
-    assert_eq!(attributes.is_ok(), true);
+    assert!(attributes.is_ok());
 
     let attributes = attributes.unwrap();
-    assert_eq!(attributes.is_empty(), false);
+    assert!(!attributes.is_empty());
     assert_eq!(attributes.id.unwrap(), "test");
     assert_eq!(attributes.sys, Some(vec!["macos".to_string()]));
-    assert_eq!(attributes.arch.is_none(), true);
-    assert_eq!(attributes.cmd.is_none(), true);
+    assert!(attributes.arch.is_none());
+    assert!(attributes.cmd.is_none());
     assert_eq!(
         attributes.args,
         Some(vec!["test1".to_string(), "test2".to_string()])
     );
 }
+
+#[test]
+fn default_attributes_test() {
+    let mut attributes = Attributes::parse("{}").unwrap();
+    assert!(attributes.generated_id);
+    attributes.id = None;
+    attributes.generated_id = false;
+    assert_eq!(attributes, Attributes::default());
+}
+
+#[test]
+fn parse_output_attributes_test() {
+    let attributes = Attributes::parse("{for=install-step, match=regex}").unwrap();
+    assert_eq!(attributes.for_id, Some("install-step".to_string()));
+    assert_eq!(attributes.matching, OutputMatch::Regex);
+
+    let attributes = Attributes::parse("{for=install-step}").unwrap();
+    assert_eq!(attributes.matching, OutputMatch::Exact);
+}
+
+#[test]
+fn parse_capture_test() {
+    let attributes = Attributes::parse("{capture=RESOURCE_ID}").unwrap();
+    assert_eq!(attributes.capture, Some("RESOURCE_ID".to_string()));
+
+    assert_eq!(
+        Attributes::parse("{capture=\"resource-id\"}")
+            .unwrap_err()
+            .to_string(),
+        "`resource-id` can't be captured, the variable names are made of letters, digits and `_`"
+    );
+}
+
+#[test]
+fn parse_timeout_test() {
+    let attributes = Attributes::parse("{timeout=30s}").unwrap();
+    assert_eq!(attributes.timeout().unwrap(), Some(Duration::from_secs(30)));
+
+    let attributes = Attributes::parse("{timeout=\"1m 30s\"}").unwrap();
+    assert_eq!(attributes.timeout().unwrap(), Some(Duration::from_secs(90)));
+
+    let attributes = Attributes::parse("{timeout=forever}").unwrap();
+    assert!(attributes.timeout().is_err());
+}
+
+#[test]
+fn parse_retries_test() {
+    let attributes = Attributes::parse("{retries=3, retry_delay=500ms}").unwrap();
+    assert_eq!(attributes.retries, 3);
+    assert_eq!(
+        attributes.retry_delay().unwrap(),
+        Duration::from_millis(500)
+    );
+
+    let attributes = Attributes::parse("").unwrap();
+    assert_eq!(attributes.retries, 0);
+    assert_eq!(attributes.retry_delay().unwrap(), Duration::ZERO);
+}
+
+#[test]
+fn parse_depends_on_test() {
+    let attributes = Attributes::parse("{id=test, depends_on=[install, build]}").unwrap();
+    assert_eq!(
+        attributes.depends_on,
+        Some(vec!["install".to_string(), "build".to_string()])
+    );
+}
+
+#[test]
+fn parse_with_defaults_test() {
+    let defaults = r#"{"timeout": "30s", "allow_warnings": false, "retries": 2}"#;
+    let attributes = Attributes::parse_with_defaults("{id=test, retries=1}", defaults).unwrap();
+    assert_eq!(attributes.timeout().unwrap(), Some(Duration::from_secs(30)));
+    assert!(!attributes.allow_warnings);
+    assert_eq!(attributes.retries, 1);
+    assert_eq!(attributes.id, Some("test".to_string()));
+}
+
+#[test]
+fn parse_env_test() {
+    let attributes =
+        Attributes::parse("{env={RUST_LOG=debug, PORT=8080}, path_prepend=[bin]}").unwrap();
+    let env = attributes.env.unwrap();
+    assert_eq!(env.get("RUST_LOG"), Some(&"debug".to_string()));
+    assert_eq!(env.get("PORT"), Some(&"8080".to_string()));
+    assert_eq!(attributes.path_prepend, Some(vec!["bin".to_string()]));
+}
+
+#[test]
+fn parse_cwd_test() {
+    let attributes = Attributes::parse("{cwd=examples/web, workdir=markdown}").unwrap();
+    assert_eq!(attributes.cwd, Some("examples/web".to_string()));
+    assert_eq!(attributes.workdir, Some(WorkDir::Markdown));
+}
+
+#[test]
+fn parse_build_test() {
+    let attributes =
+        Attributes::parse(r#"{build=["gcc", "-O2", "{file}", "-o", "{bin}"]}"#).unwrap();
+    assert_eq!(
+        attributes.build.map(|b| b.join(" ")),
+        Some("gcc -O2 {file} -o {bin}".to_string())
+    );
+}
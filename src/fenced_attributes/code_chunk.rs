@@ -6,7 +6,7 @@ use crate::fenced_attributes::attributes::Attributes;
 lazy_static! {
     pub static ref CODE_FENCED_CHUNK: Regex = Regex::new(r"^ *(`{3,}([^\n]*)|~{3,})").unwrap();
     pub static ref CODE_CHUNK_IN_BRACKETS: Regex =
-        Regex::new(r"^ *(`{3,}|~{3,})([^\s{]*)\s*\{(.*?)\}").unwrap();
+        Regex::new(r"^ *(`{3,}|~{3,})([^\s{]*)\s*\{(.*)\}").unwrap();
     pub static ref ONLY_LANG: Regex = Regex::new(r"^ *(`{3,}|~{3,})([^\s]+)").unwrap();
 }
 
@@ -18,11 +18,16 @@ pub struct CodeChunk {
 
     /// code chunk attributes
     pub attributes: Attributes,
+
+    /// why the attributes couldn't be parsed, the code chunk failing with
+    /// this error instead of running with the default attributes
+    pub error: Option<String>,
 }
 
 impl CodeChunk {
-    /// build a CodeChunk from a string containing the fences line
-    pub fn parse_fences(line: String) -> Option<Self> {
+    /// build a CodeChunk from a string containing the fences line,
+    /// its attributes on top of the given default attributes
+    pub fn parse_fences(line: String, defaults: &str) -> Option<Self> {
         if CODE_FENCED_CHUNK.is_match(&line) {
             let line = line.trim();
             let captures = if line.contains("{") {
@@ -32,15 +37,22 @@ impl CodeChunk {
             };
             let (language, attributes_string) = captures.map_or((None, None), |m| {
                 let lang = m.get(2).map(|v| v.as_str());
-                let attrs = m.get(3).map(|v| v.as_str());
+                let attrs = m.get(3).map(|v| inside_braces(v.as_str()));
                 (lang, attrs)
             });
             language.map(|_l| {
                 let attr = attributes_string.unwrap_or("");
-                let attributes = Attributes::parse(attr).unwrap_or_default();
+                let (attributes, error) = match Attributes::parse_with_defaults(attr, defaults) {
+                    Ok(attributes) => (attributes, None),
+                    Err(e) => (
+                        Attributes::with_generated_id(Attributes::default()),
+                        Some(format!("invalid attributes `{{{attr}}}`: {e}")),
+                    ),
+                };
                 CodeChunk {
-                    lang: language.map(|l| String::from(l)),
+                    lang: language.map(String::from),
                     attributes,
+                    error,
                 }
             })
         } else {
@@ -49,19 +61,59 @@ impl CodeChunk {
     }
 }
 
+/// the attributes text up to the brace closing the attributes, as they can
+/// contain braces themselves, e.g. `env={KEY=value}` or `"${VAR}"`
+fn inside_braces(text: &str) -> &str {
+    let mut depth = 0;
+    let mut quoted = false;
+    for (idx, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '{' if !quoted => depth += 1,
+            '}' if !quoted && depth == 0 => return &text[..idx],
+            '}' if !quoted => depth -= 1,
+            _ => {}
+        }
+    }
+    text
+}
+
 #[test]
 fn indented_code_fences() {
     let fences = String::from("```");
-    let chunk = CodeChunk::parse_fences(fences);
-    assert_eq!(chunk.is_none(), true);
+    let chunk = CodeChunk::parse_fences(fences, "");
+    assert!(chunk.is_none());
 
     let fences = String::from("```java");
-    let chunk = CodeChunk::parse_fences(fences).unwrap();
+    let chunk = CodeChunk::parse_fences(fences, "").unwrap();
     assert_eq!(chunk.lang, Some(String::from("java")));
-    assert_eq!(chunk.attributes.is_empty(), true);
+    assert!(chunk.attributes.is_empty());
 
     let fences = String::from("```bash {id: test, sys=[macos], args=[test1, test2]}}");
-    let chunk = CodeChunk::parse_fences(fences).unwrap();
+    let chunk = CodeChunk::parse_fences(fences, "").unwrap();
     assert_eq!(chunk.lang, Some(String::from("bash")));
-    assert_eq!(chunk.attributes.is_empty(), false);
+    assert!(!chunk.attributes.is_empty());
+}
+
+#[test]
+fn nested_braces_in_attributes() {
+    let fences = String::from(r#"```sh {env={URL="${HOST}/x"}, id=test} trailing }"#);
+    let chunk = CodeChunk::parse_fences(fences, "").unwrap();
+    assert_eq!(chunk.attributes.id, Some(String::from("test")));
+    assert_eq!(
+        chunk.attributes.env.unwrap().get("URL"),
+        Some(&String::from("${HOST}/x"))
+    );
+}
+
+#[test]
+fn invalid_attributes() {
+    let fences = String::from("```sh {id=x, retries=abc}");
+    let chunk = CodeChunk::parse_fences(fences, "").unwrap();
+    assert!(chunk
+        .error
+        .unwrap()
+        .starts_with("invalid attributes `{id=x, retries=abc}`"));
+    assert!(chunk.attributes.generated_id);
+    assert!(chunk.attributes.eval && chunk.attributes.echo && chunk.attributes.include);
 }
@@ -0,0 +1,499 @@
+use std::collections::VecDeque;
+use std::io::{stderr, Write};
+
+use anyhow::Result;
+
+use crate::cli::args::Format;
+use crate::executor::command_output::{CommandExecInfo, CommandStream};
+use crate::executor::execution_plan::{ExecutionItem, ExecutionPlan};
+use crate::executor::job_location::JobLocation;
+use crate::executor::{Executor, ExecutorOptions};
+use crate::report::{ndjson, timed_out, RunReport};
+
+/// Run the execution plan without the terminal UI.
+///
+/// With the plain format, the markdown and the stdout of the code chunks
+/// are written to `w` as plain text, the stderr of the code chunks goes to
+/// stderr. With the ndjson format, every piece of information about the
+/// execution is written to `w` as a line of JSON.
+pub fn run<W: Write>(
+    w: &mut W,
+    location: JobLocation,
+    execution_plan: ExecutionPlan,
+    options: ExecutorOptions,
+    format: Format,
+    report: &mut RunReport,
+) -> Result<()> {
+    let executor = Executor::new(location, execution_plan, options)?;
+
+    let mut printer = PlainPrinter::default();
+
+    for info in executor.line_receiver.iter() {
+        report.record(&info);
+        let done = matches!(
+            info,
+            CommandExecInfo::Finished | CommandExecInfo::Interruption
+        );
+        match format {
+            Format::Plain if report.shows(&info) => printer.write(w, info)?,
+            Format::Plain => printer.hide(w, &info)?,
+            Format::Ndjson => ndjson::write_event(w, &info)?,
+        }
+        w.flush()?;
+        if done {
+            break;
+        }
+    }
+    printer.finish(w)?;
+    w.flush()?;
+    executor.die()?;
+    Ok(())
+}
+
+/// a line of plain text to write
+enum Piece {
+    /// a line of the markdown, with whether it's in a code block
+    Markdown(String, bool),
+    /// a line out of the code blocks
+    Text(String),
+    /// a line of the stderr of a code chunk
+    Stderr(String),
+}
+
+/// the lines about a code chunk, or the markdown between code chunks
+struct Segment {
+    /// id of the code chunk, none for the markdown
+    chunk: Option<String>,
+    /// id of the code chunk and line of the expected output block whose
+    /// result goes in the segment, once the code chunk ran
+    expected: Option<(String, usize)>,
+    pieces: Vec<Piece>,
+    /// whether no line will be added to the segment
+    closed: bool,
+}
+
+/// writes the execution information as plain text, in document order:
+/// the lines of a code chunk running in parallel are held back until
+/// everything before it is written
+#[derive(Default)]
+struct PlainPrinter {
+    /// whether a code block is being written
+    in_code: bool,
+    segments: VecDeque<Segment>,
+}
+
+impl PlainPrinter {
+    fn write<W: Write>(&mut self, w: &mut W, info: CommandExecInfo) -> Result<()> {
+        match info {
+            CommandExecInfo::Output(item) => {
+                let piece = match item {
+                    ExecutionItem::OutputString(line) => Piece::Markdown(line, false),
+                    ExecutionItem::OutputCode(line) => Piece::Markdown(line, true),
+                    ExecutionItem::Expect(expected) => {
+                        // the code chunk still runs, its result comes later
+                        let for_id = expected
+                            .code_chunk
+                            .and_then(|c| c.attributes.for_id)
+                            .unwrap_or_default();
+                        self.open(None, Some((for_id, expected.position.start)), vec![]);
+                        return self.flush(w);
+                    }
+                    ExecutionItem::Execute(_) | ExecutionItem::Skip(..) => return Ok(()),
+                };
+                self.push(None, piece);
+            }
+            CommandExecInfo::Start { chunk } => {
+                info!("execution of {} started", chunk.id);
+                let pieces = vec![Piece::Text("~~~~~~~~~".to_string())];
+                self.open(Some(chunk.id), None, pieces);
+            }
+            CommandExecInfo::Skipped { chunk, reason } => {
+                info!("{} skipped: {}", chunk.id, reason);
+                self.push(None, Piece::Text(format!("~~~~~~~~~ skipped, {reason}")));
+                self.close_expected(&chunk.id, None);
+            }
+            CommandExecInfo::Line { chunk, line } => {
+                let piece = match line.origin {
+                    CommandStream::StdOut => Piece::Text(line.content),
+                    CommandStream::StdErr => Piece::Stderr(line.content),
+                };
+                self.push(Some(&chunk.id), piece);
+            }
+            CommandExecInfo::End {
+                chunk,
+                status,
+                passed,
+                ..
+            } => {
+                info!("execution finished with status: {:?}", status);
+                let line = match status {
+                    Some(status) if passed && !status.success() => {
+                        format!("~~~~~~~~~ {status}, errors allowed")
+                    }
+                    Some(status) if passed => format!("~~~~~~~~~ {status}"),
+                    Some(status) if status.success() => {
+                        format!("~~~~~~~~~ failed, {status} with warnings on stderr")
+                    }
+                    Some(status) => format!("~~~~~~~~~ failed, {status}"),
+                    None if passed => "~~~~~~~~~ passed, no exit status".to_string(),
+                    None => "~~~~~~~~~ failed, no exit status".to_string(),
+                };
+                self.push(Some(&chunk.id), Piece::Text(line));
+                self.close(&chunk.id);
+            }
+            CommandExecInfo::Built {
+                chunk,
+                status,
+                passed,
+                ..
+            } => {
+                info!("build of {} finished with status: {:?}", chunk.id, status);
+                let line = match status {
+                    Some(_) if passed => "~~~~~~~~~ built".to_string(),
+                    Some(status) => format!("~~~~~~~~~ build failed, {status}"),
+                    None => "~~~~~~~~~ build failed, no exit status".to_string(),
+                };
+                self.push(Some(&chunk.id), Piece::Text(line));
+                if !passed {
+                    self.close(&chunk.id);
+                }
+            }
+            CommandExecInfo::Captured { chunk, name, value } => {
+                info!("{} captured {}", chunk.id, name);
+                let line = format!("~~~~~~~~~ captured {name}={value}");
+                self.push(Some(&chunk.id), Piece::Text(line));
+            }
+            CommandExecInfo::Retry {
+                chunk,
+                attempt,
+                reason,
+                delay,
+            } => {
+                info!("attempt {} of {} failed", attempt, chunk.id);
+                let line = format!(
+                    "~~~~~~~~~ attempt {attempt} failed, {reason}, retrying in {}",
+                    humantime::format_duration(delay)
+                );
+                self.push(Some(&chunk.id), Piece::Text(line));
+            }
+            CommandExecInfo::TimedOut { chunk, timeout, .. } => {
+                info!("{} timed out", chunk.id);
+                let line = format!("~~~~~~~~~ failed, {}", timed_out(&timeout));
+                self.push(Some(&chunk.id), Piece::Text(line));
+                self.close(&chunk.id);
+            }
+            CommandExecInfo::Assertion {
+                chunk,
+                expected,
+                diff,
+                ..
+            } => {
+                let line = expected.start + 1;
+                let mut pieces = vec![];
+                match diff {
+                    None => pieces.push(Piece::Text(format!(
+                        "~~~~~~~~~ output of {} matches line {line}",
+                        chunk.id
+                    ))),
+                    Some(diff) => {
+                        pieces.push(Piece::Text(format!(
+                            "~~~~~~~~~ output of {} doesn't match line {line}",
+                            chunk.id
+                        )));
+                        pieces.extend(diff.lines().map(|l| Piece::Text(l.to_string())));
+                    }
+                }
+                match self.close_expected(&chunk.id, Some(expected.start)) {
+                    Some(idx) => self.segments[idx].pieces.extend(pieces),
+                    None => {
+                        for piece in pieces {
+                            self.push(None, piece);
+                        }
+                    }
+                }
+            }
+            CommandExecInfo::Error { chunk, message } => {
+                warn!("error in computation: {}", message);
+                let id = chunk.map(|c| c.id);
+                self.push(
+                    id.as_ref(),
+                    Piece::Text(format!("~~~~~~~~~ error: {message}")),
+                );
+                if let Some(id) = id {
+                    self.close(&id);
+                }
+            }
+            CommandExecInfo::Interruption => {
+                debug!("command was interrupted (by us)");
+            }
+            CommandExecInfo::Finished => {
+                info!("execution plan finished");
+            }
+        }
+        self.flush(w)
+    }
+
+    /// follow the information not shown, about the hidden code chunks
+    fn hide<W: Write>(&mut self, w: &mut W, info: &CommandExecInfo) -> Result<()> {
+        match info {
+            CommandExecInfo::Assertion {
+                chunk, expected, ..
+            } => {
+                self.close_expected(&chunk.id, Some(expected.start));
+            }
+            CommandExecInfo::Skipped { chunk, .. } => {
+                self.close_expected(&chunk.id, None);
+            }
+            _ => return Ok(()),
+        }
+        self.flush(w)
+    }
+
+    /// start a segment after the markdown written so far
+    fn open(
+        &mut self,
+        chunk: Option<String>,
+        expected: Option<(String, usize)>,
+        pieces: Vec<Piece>,
+    ) {
+        if let Some(last) = self.segments.back_mut() {
+            if last.chunk.is_none() && last.expected.is_none() {
+                last.closed = true;
+            }
+        }
+        self.segments.push_back(Segment {
+            chunk,
+            expected,
+            pieces,
+            closed: false,
+        });
+    }
+
+    /// add a line to the open segment of the code chunk, or to the markdown
+    fn push(&mut self, chunk: Option<&String>, piece: Piece) {
+        let idx = match chunk {
+            Some(id) => self
+                .segments
+                .iter()
+                .rposition(|s| !s.closed && s.chunk.as_ref() == Some(id)),
+            None => match self.segments.back() {
+                Some(last) if last.chunk.is_none() && last.expected.is_none() && !last.closed => {
+                    Some(self.segments.len() - 1)
+                }
+                _ => None,
+            },
+        };
+        match idx {
+            Some(idx) => self.segments[idx].pieces.push(piece),
+            None => {
+                // the markdown after a code chunk, or a line of a code
+                // chunk which didn't start
+                self.segments.push_back(Segment {
+                    chunk: None,
+                    expected: None,
+                    pieces: vec![piece],
+                    closed: false,
+                });
+            }
+        }
+    }
+
+    /// mark as complete the segments waiting for the result of the expected
+    /// output blocks of the code chunk, all of them or the one at the given
+    /// line, returning the position of the last one
+    fn close_expected(&mut self, chunk: &str, line: Option<usize>) -> Option<usize> {
+        let mut found = None;
+        for (idx, segment) in self.segments.iter_mut().enumerate() {
+            let waits = segment
+                .expected
+                .as_ref()
+                .is_some_and(|(id, l)| id == chunk && (line.is_none() || line == Some(*l)));
+            if waits && !segment.closed {
+                segment.closed = true;
+                found = Some(idx);
+            }
+        }
+        found
+    }
+
+    /// mark the segment of the code chunk as complete
+    fn close(&mut self, chunk: &String) {
+        if let Some(segment) = self
+            .segments
+            .iter_mut()
+            .rev()
+            .find(|s| s.chunk.as_ref() == Some(chunk))
+        {
+            segment.closed = true;
+        }
+    }
+
+    /// write the lines which come, in document order, before any running code chunk
+    fn flush<W: Write>(&mut self, w: &mut W) -> Result<()> {
+        while let Some(segment) = self.segments.front_mut() {
+            let pieces: Vec<Piece> = segment.pieces.drain(..).collect();
+            let closed = segment.closed;
+            for piece in pieces {
+                self.write_piece(w, piece)?;
+            }
+            if !closed {
+                break;
+            }
+            self.segments.pop_front();
+        }
+        Ok(())
+    }
+
+    /// write all the remaining lines, the code chunks being over
+    fn finish<W: Write>(&mut self, w: &mut W) -> Result<()> {
+        for segment in &mut self.segments {
+            segment.closed = true;
+        }
+        self.flush(w)?;
+        self.close_code(w)
+    }
+
+    fn write_piece<W: Write>(&mut self, w: &mut W, piece: Piece) -> Result<()> {
+        match piece {
+            Piece::Markdown(line, is_code) => {
+                if is_code != self.in_code {
+                    writeln!(w, "```")?;
+                    self.in_code = is_code;
+                }
+                writeln!(w, "{line}")?;
+            }
+            Piece::Text(line) => {
+                self.close_code(w)?;
+                writeln!(w, "{line}")?;
+            }
+            Piece::Stderr(line) => {
+                w.flush()?;
+                writeln!(stderr(), "{line}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// close the code fence if a code block is being written
+    fn close_code<W: Write>(&mut self, w: &mut W) -> Result<()> {
+        if self.in_code {
+            writeln!(w, "```")?;
+            self.in_code = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use crate::executor::command_output::{
+        ChunkRef, CommandExecInfo, CommandOutputLine, CommandStream,
+    };
+    use crate::executor::executable::ExecutablePosition;
+    use crate::executor::execution_plan::{ExecutionItem, ExecutionPlan};
+    use crate::headless::PlainPrinter;
+
+    fn chunk(id: &str) -> ChunkRef {
+        ChunkRef {
+            id: id.to_string(),
+            md_file: PathBuf::from("README.md"),
+            position: ExecutablePosition::default(),
+            generated_id: false,
+        }
+    }
+
+    fn line(id: &str, content: &str) -> CommandExecInfo {
+        CommandExecInfo::Line {
+            chunk: chunk(id),
+            line: CommandOutputLine {
+                content: content.to_string(),
+                origin: CommandStream::StdOut,
+            },
+        }
+    }
+
+    fn expect(for_id: &str, start: usize) -> CommandExecInfo {
+        let md = format!("```output {{for={for_id}}}\n```");
+        let mut expected = ExecutionPlan::from(md.as_str())
+            .plan
+            .into_iter()
+            .find_map(|item| match item {
+                ExecutionItem::Expect(e) => Some(e),
+                _ => None,
+            })
+            .unwrap();
+        expected.position.start = start;
+        CommandExecInfo::Output(ExecutionItem::Expect(expected))
+    }
+
+    fn end(id: &str) -> CommandExecInfo {
+        CommandExecInfo::End {
+            chunk: chunk(id),
+            status: None,
+            passed: false,
+            duration: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn parallel_output_in_document_order() {
+        let mut printer = PlainPrinter::default();
+        let mut w: Vec<u8> = vec![];
+        let infos = vec![
+            CommandExecInfo::Start { chunk: chunk("a") },
+            CommandExecInfo::Output(ExecutionItem::OutputString("between".to_string())),
+            CommandExecInfo::Start { chunk: chunk("b") },
+            line("b", "b1"),
+            line("a", "a1"),
+            end("b"),
+            line("a", "a2"),
+            end("a"),
+            CommandExecInfo::Output(ExecutionItem::OutputString("after".to_string())),
+        ];
+        for info in infos {
+            printer.write(&mut w, info).unwrap();
+        }
+        printer.finish(&mut w).unwrap();
+
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "~~~~~~~~~\na1\na2\n~~~~~~~~~ failed, no exit status\nbetween\n~~~~~~~~~\nb1\n~~~~~~~~~ failed, no exit status\nafter\n"
+        );
+    }
+
+    #[test]
+    fn assertion_at_its_output_block() {
+        let mut printer = PlainPrinter::default();
+        let mut w: Vec<u8> = vec![];
+        let infos = vec![
+            CommandExecInfo::Start { chunk: chunk("a") },
+            CommandExecInfo::Output(ExecutionItem::OutputCode("one".to_string())),
+            expect("a", 6),
+            CommandExecInfo::Output(ExecutionItem::OutputCode("echo two".to_string())),
+            CommandExecInfo::Start { chunk: chunk("b") },
+            line("a", "one"),
+            end("a"),
+            CommandExecInfo::Assertion {
+                chunk: chunk("a"),
+                expected: ExecutablePosition::start(6),
+                matching: Default::default(),
+                passed: true,
+                diff: None,
+            },
+            line("b", "two"),
+            end("b"),
+        ];
+        for info in infos {
+            printer.write(&mut w, info).unwrap();
+        }
+        printer.finish(&mut w).unwrap();
+
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "~~~~~~~~~\none\n~~~~~~~~~ failed, no exit status\n```\none\n```\n~~~~~~~~~ output of a matches line 7\n```\necho two\n```\n~~~~~~~~~\ntwo\n~~~~~~~~~ failed, no exit status\n"
+        );
+    }
+}
@@ -1,14 +1,16 @@
 mod app;
 mod cli;
 mod executor;
+mod export;
 mod fenced_attributes;
+mod headless;
+mod report;
 mod view;
 
 #[macro_use]
 extern crate log;
 
 use crate::cli::args::Args;
-use anyhow;
 use clap::Parser;
 use log::LevelFilter;
 use log4rs::append::file::FileAppender;
@@ -41,16 +43,17 @@ fn main() -> anyhow::Result<()> {
     } else {
         LevelFilter::Error
     };
-    let config = if logfile.is_some() {
+    let config = if let Some(logfile) = logfile {
         config_builder
-            .appender(Appender::builder().build("logfile", Box::new(logfile.unwrap())))
+            .appender(Appender::builder().build("logfile", Box::new(logfile)))
             .build(Root::builder().appender("logfile").build(log_level))?
     } else {
         config_builder.build(Root::builder().build(log_level))?
     };
 
     log4rs::init_config(config)?;
-    cli::run(&args)?;
+    executor::process_group::kill_on_signals();
+    let code = cli::run(&args)?;
     info!("bye");
-    Ok(())
+    std::process::exit(code)
 }
@@ -0,0 +1,370 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::executor::command_output::CommandStream;
+use crate::report::{timed_out, ChunkOutcome, ChunkReport, RunReport};
+
+/// write the report as JUnit XML into the given file
+pub fn write_file(report: &RunReport, path: &Path) -> Result<()> {
+    fs::write(path, render(report)?)?;
+    Ok(())
+}
+
+/// render the report as JUnit XML, one testsuite per markdown
+/// file and one testcase per code chunk, the errors not related to
+/// a code chunk being testcases of their own
+pub fn render(report: &RunReport) -> Result<String> {
+    let total_time: Duration = report.chunks.iter().map(|c| c.duration).sum();
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        xml,
+        r#"<testsuites name="markdown-gem" tests="{}" failures="{}" errors="{}" skipped="{}" time="{}">"#,
+        report.chunks.len() + report.errors.len(),
+        count(report.chunks.iter(), is_failure),
+        count(report.chunks.iter(), is_error) + report.errors.len(),
+        count(report.chunks.iter(), is_skipped),
+        seconds(total_time),
+    )?;
+    let mut suites = report.by_md_file();
+    for (md_file, _) in &report.errors {
+        if !suites.iter().any(|(f, _)| *f == md_file) {
+            suites.push((md_file, vec![]));
+        }
+    }
+    for (md_file, chunks) in suites {
+        let suite_name = escape(&md_file.to_string_lossy());
+        let suite_time: Duration = chunks.iter().map(|c| c.duration).sum();
+        let errors: Vec<&String> = report
+            .errors
+            .iter()
+            .filter(|(f, _)| f == md_file)
+            .map(|(_, message)| message)
+            .collect();
+        writeln!(
+            xml,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" errors="{}" skipped="{}" time="{}">"#,
+            suite_name,
+            chunks.len() + errors.len(),
+            count(chunks.iter().copied(), is_failure),
+            count(chunks.iter().copied(), is_error) + errors.len(),
+            count(chunks.iter().copied(), is_skipped),
+            seconds(suite_time),
+        )?;
+        for chunk in chunks {
+            write_testcase(&mut xml, &suite_name, chunk)?;
+        }
+        for message in errors {
+            writeln!(
+                xml,
+                r#"    <testcase name="error" classname="{suite_name}" time="0.000">"#
+            )?;
+            writeln!(
+                xml,
+                r#"      <error message="{}" type="execution"/>"#,
+                escape(message)
+            )?;
+            writeln!(xml, "    </testcase>")?;
+        }
+        writeln!(xml, "  </testsuite>")?;
+    }
+    writeln!(xml, "</testsuites>")?;
+    Ok(xml)
+}
+
+fn write_testcase(xml: &mut String, classname: &str, report: &ChunkReport) -> Result<()> {
+    writeln!(
+        xml,
+        r#"    <testcase name="{}" classname="{}" time="{}">"#,
+        escape(&report.chunk.name()),
+        classname,
+        seconds(report.duration),
+    )?;
+    if report.attempts > 1 || report.captured.is_some() {
+        writeln!(xml, "      <properties>")?;
+        if report.attempts > 1 {
+            writeln!(
+                xml,
+                r#"        <property name="attempts" value="{}"/>"#,
+                report.attempts
+            )?;
+        }
+        if let Some((name, value)) = &report.captured {
+            writeln!(
+                xml,
+                r#"        <property name="captured.{}" value="{}"/>"#,
+                escape(name),
+                escape(value)
+            )?;
+        }
+        writeln!(xml, "      </properties>")?;
+    }
+    match &report.outcome {
+        ChunkOutcome::Finished { passed: false, .. } => {
+            writeln!(
+                xml,
+                r#"      <failure message="{}" type="exit_status"/>"#,
+                escape(&report.outcome.failure_reason().unwrap_or_default())
+            )?;
+        }
+        ChunkOutcome::BuildFailed(_) => {
+            writeln!(
+                xml,
+                r#"      <failure message="{}" type="build"/>"#,
+                escape(&report.outcome.failure_reason().unwrap_or_default())
+            )?;
+        }
+        ChunkOutcome::TimedOut(timeout) => {
+            writeln!(
+                xml,
+                r#"      <failure message="{}" type="timeout"/>"#,
+                timed_out(timeout)
+            )?;
+        }
+        ChunkOutcome::Error(message) => {
+            writeln!(
+                xml,
+                r#"      <error message="{}" type="execution"/>"#,
+                escape(message)
+            )?;
+        }
+        ChunkOutcome::Skipped(reason) => {
+            writeln!(xml, r#"      <skipped message="{}"/>"#, escape(reason))?;
+        }
+        ChunkOutcome::Finished { passed: true, .. } | ChunkOutcome::Running => {}
+    }
+    for assertion in report.failed_assertions() {
+        writeln!(
+            xml,
+            r#"      <failure message="output doesn't match the block at line {}" type="output">{}</failure>"#,
+            assertion.expected.start + 1,
+            escape(assertion.diff.as_deref().unwrap_or_default()),
+        )?;
+    }
+    let stdout = report.output.content(CommandStream::StdOut);
+    if !stdout.is_empty() {
+        writeln!(
+            xml,
+            "      <system-out>{}</system-out>",
+            escape(&stdout.join("\n"))
+        )?;
+    }
+    let stderr = report.output.content(CommandStream::StdErr);
+    if !stderr.is_empty() {
+        writeln!(
+            xml,
+            "      <system-err>{}</system-err>",
+            escape(&stderr.join("\n"))
+        )?;
+    }
+    writeln!(xml, "    </testcase>")?;
+    Ok(())
+}
+
+fn is_failure(report: &ChunkReport) -> bool {
+    !is_error(report) && report.is_failure()
+}
+
+fn is_error(report: &ChunkReport) -> bool {
+    matches!(report.outcome, ChunkOutcome::Error(_))
+}
+
+fn is_skipped(report: &ChunkReport) -> bool {
+    matches!(report.outcome, ChunkOutcome::Skipped(_))
+}
+
+fn count<'a, I: Iterator<Item = &'a ChunkReport>>(chunks: I, f: fn(&ChunkReport) -> bool) -> usize {
+    chunks.filter(|c| f(c)).count()
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+/// escape the text for use in XML attributes and content, dropping
+/// the characters XML 1.0 doesn't allow
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use crate::executor::command_output::{ChunkRef, CommandExecInfo};
+    use crate::executor::executable::ExecutablePosition;
+    use crate::report::junit::{escape, render};
+    use crate::report::RunReport;
+
+    fn chunk(id: &str) -> ChunkRef {
+        ChunkRef {
+            id: id.to_string(),
+            md_file: PathBuf::from("docs/README.md"),
+            position: ExecutablePosition::new(1, 4),
+            generated_id: false,
+        }
+    }
+
+    #[test]
+    fn escape_xml() {
+        assert_eq!(
+            escape("<a href=\"x\">'b' & c</a>\u{1b}[0m"),
+            "&lt;a href=&quot;x&quot;&gt;&apos;b&apos; &amp; c&lt;/a&gt;[0m"
+        );
+    }
+
+    #[test]
+    fn skipped_and_errors_are_reported() {
+        let mut report = RunReport::default();
+        report.record(&CommandExecInfo::Skipped {
+            chunk: chunk("windows-only"),
+            reason: "sys filter".to_string(),
+        });
+        report.record(&CommandExecInfo::Error {
+            chunk: Some(chunk("broken")),
+            message: "failed to start task".to_string(),
+        });
+        let xml = render(&report).unwrap();
+
+        assert!(xml.contains(r#"tests="2" failures="0" errors="1" skipped="1""#));
+        assert!(xml.contains(r#"<testsuite name="docs/README.md""#));
+        assert!(xml.contains(r#"<testcase name="windows-only" classname="docs/README.md""#));
+        assert!(xml.contains(r#"<skipped message="sys filter"/>"#));
+        assert!(xml.contains(r#"<error message="failed to start task" type="execution"/>"#));
+    }
+
+    #[test]
+    fn errors_without_chunk_are_testcases() {
+        let mut report = RunReport::default();
+        report.start_file(Path::new("docs/README.md"));
+        report.record(&CommandExecInfo::Error {
+            chunk: None,
+            message: "the output block at line 3 expects the output of `a`".to_string(),
+        });
+        let xml = render(&report).unwrap();
+
+        assert!(xml.contains(
+            r#"<testsuites name="markdown-gem" tests="1" failures="0" errors="1" skipped="0""#
+        ));
+        assert!(xml.contains(
+            r#"<testsuite name="docs/README.md" tests="1" failures="0" errors="1" skipped="0""#
+        ));
+        assert!(xml.contains(r#"<testcase name="error" classname="docs/README.md""#));
+        assert!(xml.contains(
+            r#"<error message="the output block at line 3 expects the output of `a`" type="execution"/>"#
+        ));
+    }
+
+    #[test]
+    fn generated_ids_are_not_names() {
+        let mut report = RunReport::default();
+        let mut generated = chunk("7f0c6a8e-7a4e-4c1e-9a51-3f1d2b6c9e10");
+        generated.generated_id = true;
+        report.record(&CommandExecInfo::Start { chunk: generated });
+        let xml = render(&report).unwrap();
+
+        assert!(xml.contains(r#"<testcase name="docs/README.md:2" classname="docs/README.md""#));
+        assert!(!xml.contains("7f0c6a8e"));
+    }
+
+    #[test]
+    fn timed_out_is_a_failure() {
+        let mut report = RunReport::default();
+        report.record(&CommandExecInfo::Start {
+            chunk: chunk("ping"),
+        });
+        report.record(&CommandExecInfo::TimedOut {
+            chunk: chunk("ping"),
+            timeout: Duration::from_secs(90),
+            duration: Duration::from_millis(90_250),
+        });
+        let xml = render(&report).unwrap();
+
+        assert!(xml.contains(r#"tests="1" failures="1" errors="0" skipped="0" time="90.250""#));
+        assert!(xml.contains(r#"<failure message="timed out after 1m 30s" type="timeout"/>"#));
+        assert!(!xml.contains("attempts"));
+    }
+
+    #[test]
+    fn build_failure() {
+        let mut report = RunReport::default();
+        report.record(&CommandExecInfo::Start {
+            chunk: chunk("hello-c"),
+        });
+        report.record(&CommandExecInfo::Built {
+            chunk: chunk("hello-c"),
+            status: None,
+            passed: false,
+            duration: Duration::from_secs(2),
+        });
+        let xml = render(&report).unwrap();
+
+        assert!(xml.contains(r#"tests="1" failures="1" errors="0" skipped="0" time="2.000""#));
+        assert!(xml.contains(r#"<failure message="build failed, no exit status" type="build"/>"#));
+    }
+
+    #[test]
+    fn captured_values_are_properties() {
+        let mut report = RunReport::default();
+        report.record(&CommandExecInfo::Start {
+            chunk: chunk("create"),
+        });
+        report.record(&CommandExecInfo::Captured {
+            chunk: chunk("create"),
+            name: "RESOURCE_ID".to_string(),
+            value: "r<42>".to_string(),
+        });
+        let xml = render(&report).unwrap();
+
+        assert_eq!(
+            report.chunks[0].captured.as_ref().map(|c| c.0.as_str()),
+            Some("RESOURCE_ID")
+        );
+        assert!(xml.contains(r#"<property name="captured.RESOURCE_ID" value="r&lt;42&gt;"/>"#));
+        assert!(!xml.contains("attempts"));
+    }
+
+    #[test]
+    fn retried_attempts() {
+        let mut report = RunReport::default();
+        report.record(&CommandExecInfo::Start {
+            chunk: chunk("install"),
+        });
+        report.record(&CommandExecInfo::Retry {
+            chunk: chunk("install"),
+            attempt: 1,
+            reason: "exit status: 1".to_string(),
+            delay: Duration::ZERO,
+        });
+        report.record(&CommandExecInfo::End {
+            chunk: chunk("install"),
+            status: None,
+            passed: false,
+            duration: Duration::ZERO,
+        });
+
+        assert_eq!(report.chunks.len(), 1);
+        assert_eq!(report.chunks[0].attempts, 2);
+        assert!(render(&report)
+            .unwrap()
+            .contains(r#"<property name="attempts" value="2"/>"#));
+    }
+}
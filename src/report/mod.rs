@@ -0,0 +1,296 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use crate::executor::command_output::{ChunkRef, CommandExecInfo, CommandOutput};
+use crate::executor::executable::ExecutablePosition;
+use crate::fenced_attributes::{OutputMatch, Visibility};
+
+pub(crate) mod junit;
+pub(crate) mod ndjson;
+
+/// outcome of a code chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkOutcome {
+    /// the code chunk is still running
+    Running,
+    /// the command ended, with its exit status if it could be retrieved,
+    /// and whether it passed considering `allow_errors` and `allow_warnings`
+    Finished {
+        status: Option<ExitStatus>,
+        passed: bool,
+    },
+    /// markdown-gem failed to execute the code chunk
+    Error(String),
+    /// the code chunk was filtered out, with the reason
+    Skipped(String),
+    /// the command was killed after running longer than its timeout
+    TimedOut(Duration),
+    /// the build phase failed, with its exit status if it could be retrieved
+    BuildFailed(Option<ExitStatus>),
+}
+
+/// how a timed out code chunk is described
+pub fn timed_out(timeout: &Duration) -> String {
+    format!("timed out after {}", humantime::format_duration(*timeout))
+}
+
+impl ChunkOutcome {
+    pub fn is_failure(&self) -> bool {
+        self.failure_reason().is_some()
+    }
+
+    /// why the code chunk failed, if it did
+    pub fn failure_reason(&self) -> Option<String> {
+        match self {
+            Self::Finished { passed: true, .. } | Self::Running | Self::Skipped(_) => None,
+            Self::Finished { status: None, .. } => Some("no exit status".to_string()),
+            Self::Finished {
+                status: Some(status),
+                ..
+            } if status.success() => Some("warnings on stderr".to_string()),
+            Self::Finished {
+                status: Some(status),
+                ..
+            } => Some(status.to_string()),
+            Self::Error(message) => Some(format!("error: {message}")),
+            Self::TimedOut(timeout) => Some(timed_out(timeout)),
+            Self::BuildFailed(Some(status)) => Some(format!("build failed, {status}")),
+            Self::BuildFailed(None) => Some("build failed, no exit status".to_string()),
+        }
+    }
+}
+
+/// result of the comparison of a code chunk output with an expected output block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssertionReport {
+    /// position of the expected output block
+    pub expected: ExecutablePosition,
+    pub matching: OutputMatch,
+    /// the diff or the reason of the failure, if the output doesn't match
+    pub diff: Option<String>,
+}
+
+/// what happened to a code chunk during the run
+#[derive(Debug, Clone)]
+pub struct ChunkReport {
+    pub chunk: ChunkRef,
+    pub outcome: ChunkOutcome,
+    pub duration: Duration,
+    pub output: CommandOutput,
+    pub assertions: Vec<AssertionReport>,
+    /// number of times the code chunk ran, the last attempt deciding the outcome
+    pub attempts: usize,
+    /// the variable captured from stdout, with its value
+    pub captured: Option<(String, String)>,
+}
+
+impl ChunkReport {
+    fn new(chunk: ChunkRef) -> Self {
+        Self {
+            chunk,
+            outcome: ChunkOutcome::Running,
+            duration: Duration::ZERO,
+            output: CommandOutput::default(),
+            assertions: vec![],
+            attempts: 1,
+            captured: None,
+        }
+    }
+
+    /// the expected output blocks which don't match the output
+    pub fn failed_assertions(&self) -> impl Iterator<Item = &AssertionReport> {
+        self.assertions.iter().filter(|a| a.diff.is_some())
+    }
+
+    pub fn is_failure(&self) -> bool {
+        self.outcome.is_failure() || self.failed_assertions().next().is_some()
+    }
+}
+
+/// the reports of all the code chunks of a run, built from
+/// the information sent by the executor, in the order of execution
+#[derive(Debug, Default)]
+pub struct RunReport {
+    pub chunks: Vec<ChunkReport>,
+    /// the errors not related to a code chunk, with the markdown file they're about
+    pub errors: Vec<(PathBuf, String)>,
+    /// the markdown file being run
+    md_file: PathBuf,
+    /// the code chunks not fully shown, with `echo=false` or `include=false`
+    pub hidden: Vec<(PathBuf, ExecutablePosition, Visibility)>,
+}
+
+impl RunReport {
+    /// update the report with a piece of information coming from the executor
+    pub fn record(&mut self, info: &CommandExecInfo) {
+        match info {
+            CommandExecInfo::Start { chunk } => {
+                self.chunks.push(ChunkReport::new(chunk.clone()));
+            }
+            CommandExecInfo::Line { chunk, line } => {
+                self.chunk_mut(chunk).output.lines.push(line.clone());
+            }
+            CommandExecInfo::End {
+                chunk,
+                status,
+                passed,
+                duration,
+            } => {
+                let report = self.chunk_mut(chunk);
+                report.outcome = ChunkOutcome::Finished {
+                    status: *status,
+                    passed: *passed,
+                };
+                report.duration = *duration;
+            }
+            CommandExecInfo::Built {
+                chunk,
+                status,
+                passed: false,
+                duration,
+            } => {
+                let report = self.chunk_mut(chunk);
+                report.outcome = ChunkOutcome::BuildFailed(*status);
+                report.duration = *duration;
+            }
+            CommandExecInfo::Captured { chunk, name, value } => {
+                self.chunk_mut(chunk).captured = Some((name.clone(), value.clone()));
+            }
+            CommandExecInfo::Retry { chunk, .. } => {
+                // only the last attempt is reported
+                let report = self.chunk_mut(chunk);
+                report.attempts += 1;
+                report.output = CommandOutput::default();
+                report.captured = None;
+            }
+            CommandExecInfo::TimedOut {
+                chunk,
+                timeout,
+                duration,
+            } => {
+                let report = self.chunk_mut(chunk);
+                report.outcome = ChunkOutcome::TimedOut(*timeout);
+                report.duration = *duration;
+            }
+            CommandExecInfo::Skipped { chunk, reason } => {
+                self.chunks.push(ChunkReport {
+                    outcome: ChunkOutcome::Skipped(reason.clone()),
+                    ..ChunkReport::new(chunk.clone())
+                });
+            }
+            CommandExecInfo::Error {
+                chunk: Some(chunk),
+                message,
+            } => {
+                self.chunk_mut(chunk).outcome = ChunkOutcome::Error(message.clone());
+            }
+            CommandExecInfo::Error {
+                chunk: None,
+                message,
+            } => {
+                self.errors.push((self.md_file.clone(), message.clone()));
+            }
+            CommandExecInfo::Assertion {
+                chunk,
+                expected,
+                matching,
+                diff,
+                ..
+            } => {
+                self.chunk_mut(chunk).assertions.push(AssertionReport {
+                    expected: expected.clone(),
+                    matching: *matching,
+                    diff: diff.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// tell the report the next information is about the given markdown file
+    pub fn start_file(&mut self, md_path: &Path) {
+        self.md_file = md_path.to_path_buf();
+    }
+
+    /// remember which code chunks of a markdown file aren't fully shown
+    pub fn hide(&mut self, md_path: &Path, hidden: Vec<(ExecutablePosition, Visibility)>) {
+        self.hidden.extend(
+            hidden
+                .into_iter()
+                .map(|(position, visibility)| (md_path.to_path_buf(), position, visibility)),
+        );
+    }
+
+    /// what is shown of the code chunk at the given position of a markdown file
+    pub fn visibility(&self, md_path: &Path, position: &ExecutablePosition) -> Visibility {
+        self.hidden
+            .iter()
+            .find(|(p, pos, _)| p == md_path && pos.start == position.start)
+            .map_or(Visibility::All, |(_, _, visibility)| *visibility)
+    }
+
+    /// whether the information should be shown, the code chunks with
+    /// `include=false` being hidden unless they fail to execute
+    pub fn shows(&self, info: &CommandExecInfo) -> bool {
+        match info {
+            CommandExecInfo::Error { .. } => true,
+            _ => match info.chunk() {
+                Some(c) => self.visibility(&c.md_file, &c.position) != Visibility::Nothing,
+                None => true,
+            },
+        }
+    }
+
+    /// number of the code chunks which failed, and of the errors
+    /// not related to a code chunk
+    pub fn failures(&self) -> usize {
+        self.chunks.iter().filter(|c| c.is_failure()).count() + self.errors.len()
+    }
+
+    /// counts of the passed, failed and skipped code chunks of a markdown file
+    pub fn summary(&self, md_path: &Path) -> Summary {
+        let mut summary = Summary::default();
+        for report in self.chunks.iter().filter(|c| c.chunk.md_file == md_path) {
+            if matches!(report.outcome, ChunkOutcome::Skipped(_)) {
+                summary.skipped += 1;
+            } else if report.is_failure() {
+                summary.failed += 1;
+            } else {
+                summary.passed += 1;
+            }
+        }
+        summary
+    }
+
+    /// the chunk reports grouped by markdown file, in the order of execution
+    pub fn by_md_file(&self) -> Vec<(&PathBuf, Vec<&ChunkReport>)> {
+        let mut groups: Vec<(&PathBuf, Vec<&ChunkReport>)> = vec![];
+        for report in &self.chunks {
+            match groups.iter_mut().find(|(f, _)| *f == &report.chunk.md_file) {
+                Some((_, reports)) => reports.push(report),
+                None => groups.push((&report.chunk.md_file, vec![report])),
+            }
+        }
+        groups
+    }
+
+    /// the last report of the given chunk, created if there's none yet
+    fn chunk_mut(&mut self, chunk: &ChunkRef) -> &mut ChunkReport {
+        match self.chunks.iter().rposition(|c| &c.chunk == chunk) {
+            Some(idx) => &mut self.chunks[idx],
+            None => {
+                self.chunks.push(ChunkReport::new(chunk.clone()));
+                self.chunks.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+/// outcome counts of the code chunks of a markdown file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub passed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
@@ -0,0 +1,102 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::executor::command_output::CommandExecInfo;
+
+/// write the piece of information as one line of JSON.
+///
+/// The markdown output isn't part of the stream, only the
+/// information about the code chunks execution.
+pub fn write_event<W: Write>(w: &mut W, info: &CommandExecInfo) -> Result<()> {
+    if let CommandExecInfo::Output(_) = info {
+        return Ok(());
+    }
+    serde_json::to_writer(&mut *w, info)?;
+    writeln!(w)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use crate::executor::command_output::{
+        ChunkRef, CommandExecInfo, CommandOutputLine, CommandStream,
+    };
+    use crate::executor::executable::ExecutablePosition;
+    use crate::executor::execution_plan::ExecutionItem;
+    use crate::fenced_attributes::OutputMatch;
+    use crate::report::ndjson::write_event;
+
+    fn chunk() -> ChunkRef {
+        ChunkRef {
+            id: "install".to_string(),
+            md_file: PathBuf::from("README.md"),
+            position: ExecutablePosition::new(3, 6),
+            generated_id: false,
+        }
+    }
+
+    fn to_string(info: CommandExecInfo) -> String {
+        let mut out = vec![];
+        write_event(&mut out, &info).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn serialize_events() {
+        assert_eq!(
+            to_string(CommandExecInfo::Start { chunk: chunk() }),
+            r#"{"event":"start","id":"install","md_file":"README.md","position":{"start":3,"end":6}}
+"#
+        );
+        assert_eq!(
+            to_string(CommandExecInfo::Line {
+                chunk: chunk(),
+                line: CommandOutputLine {
+                    content: "done".to_string(),
+                    origin: CommandStream::StdErr,
+                },
+            }),
+            r#"{"event":"line","id":"install","md_file":"README.md","position":{"start":3,"end":6},"content":"done","origin":"stderr"}
+"#
+        );
+        assert_eq!(
+            to_string(CommandExecInfo::End {
+                chunk: chunk(),
+                status: None,
+                passed: false,
+                duration: Duration::from_millis(1500),
+            }),
+            r#"{"event":"end","id":"install","md_file":"README.md","position":{"start":3,"end":6},"status":null,"passed":false,"duration":1.5}
+"#
+        );
+        assert_eq!(
+            to_string(CommandExecInfo::Assertion {
+                chunk: chunk(),
+                expected: ExecutablePosition::new(7, 9),
+                matching: OutputMatch::Trimmed,
+                passed: true,
+                diff: None,
+            }),
+            r#"{"event":"assertion","id":"install","md_file":"README.md","position":{"start":3,"end":6},"expected":{"start":7,"end":9},"matching":"trimmed","passed":true,"diff":null}
+"#
+        );
+        assert_eq!(
+            to_string(CommandExecInfo::Error {
+                chunk: None,
+                message: "oops".to_string(),
+            }),
+            r#"{"event":"error","message":"oops"}
+"#
+        );
+        assert_eq!(
+            to_string(CommandExecInfo::Output(ExecutionItem::OutputString(
+                "# title".to_string()
+            ))),
+            ""
+        );
+    }
+}
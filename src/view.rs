@@ -1,9 +1,11 @@
+use std::collections::HashMap;
+
 use crossterm::style::Attribute;
 use crossterm::style::Color::{AnsiValue, Magenta, Yellow};
 
-use anyhow;
 use termimad::*;
 
+use crate::cli::config::Skin;
 use crate::cli::help_line::HelpLine;
 use crate::cli::help_page::HelpPage;
 use crate::cli::keybindings::KeyBindings;
@@ -26,7 +28,9 @@ impl From<ExecutionItem> for ViewLine {
         match item {
             ExecutionItem::OutputCode(code) => ViewLine::CodeOutput(code),
             ExecutionItem::OutputString(str) => ViewLine::LineOutput(str),
-            ExecutionItem::Execute(e) => ViewLine::LineOutput(e.code),
+            ExecutionItem::Execute(e) | ExecutionItem::Skip(e, _) | ExecutionItem::Expect(e) => {
+                ViewLine::LineOutput(e.code)
+            }
         }
     }
 }
@@ -39,6 +43,8 @@ pub struct View {
     status_skin: MadSkin,
     render_area: Area, // where the markdown will be rendered
     // status_area: Area, // where the markdown will be rendered
+    /// the key bindings of the actions
+    keybindings: KeyBindings,
     /// the tool building the help line
     help_line: HelpLine,
     /// the help page displayed over the rest, if any
@@ -46,31 +52,41 @@ pub struct View {
     show_help_page: bool,
     executing: bool,
     lines: Vec<ViewLine>,
+    /// index in `lines` of the next output line of each running code chunk
+    chunk_slots: HashMap<String, usize>,
     /// number of lines hidden on top due to scroll
     scroll: usize,
 }
 
 impl View {
-    pub fn new(keybindings: &KeyBindings) -> Self {
+    pub fn new(keybindings: &KeyBindings, skin: &Skin) -> Self {
         let mut render_skin = MadSkin::default();
-        render_skin.set_headers_fg(AnsiValue(178));
-        render_skin.bold.set_fg(Yellow);
-        render_skin.italic.set_fg(Magenta);
-        render_skin.scrollbar.thumb.set_fg(AnsiValue(178));
+        render_skin.set_headers_fg(skin.headers.map_or(AnsiValue(178), AnsiValue));
+        render_skin.bold.set_fg(skin.bold.map_or(Yellow, AnsiValue));
+        render_skin
+            .italic
+            .set_fg(skin.italic.map_or(Magenta, AnsiValue));
+        if let Some(code) = skin.code {
+            render_skin.inline_code.set_fg(AnsiValue(code));
+            render_skin.code_block.set_fg(AnsiValue(code));
+        }
         render_skin.code_block.align = Alignment::Center;
         render_skin.table.align = Alignment::Center;
-        render_skin.scrollbar.thumb.set_fg(AnsiValue(178));
+        render_skin
+            .scrollbar
+            .thumb
+            .set_fg(skin.scrollbar.map_or(AnsiValue(178), AnsiValue));
 
         let (width, height) = terminal_size();
 
-        let render_area = Area::new(0, 0, width, if height > 1 { height - 1 } else { 0 });
+        let render_area = Area::new(0, 0, width, height.saturating_sub(1));
         // let status_area = Area::new(0, height, width, 1);
         // render_area.pad_for_max_width(120); // we don't want a too wide text column
 
         let mut status_skin = MadSkin::default();
         status_skin
             .paragraph
-            .set_fgbg(AnsiValue(252), AnsiValue(239));
+            .set_fgbg(AnsiValue(252), AnsiValue(skin.status.unwrap_or(239)));
         status_skin.italic = CompoundStyle::new(Some(AnsiValue(204)), None, Attribute::Bold.into());
 
         let help_line = HelpLine::new(keybindings);
@@ -83,11 +99,13 @@ impl View {
             status_skin,
             render_area,
             // status_area,
+            keybindings: keybindings.clone(),
             help_line,
             help_page,
             show_help_page: false,
             executing: false,
             lines: vec![],
+            chunk_slots: HashMap::new(),
             scroll: 0,
         }
     }
@@ -106,6 +124,11 @@ impl View {
         self.show_help_page = !self.show_help_page;
     }
 
+    /// the key bindings of the actions
+    pub fn keybindings(&self) -> &KeyBindings {
+        &self.keybindings
+    }
+
     /// draw the grey line containing the keybindings indications
     pub fn draw_help_line(&mut self, w: &mut W) -> anyhow::Result<()> {
         let markdown = self.help_line.markdown(self);
@@ -113,7 +136,7 @@ impl View {
             self.status_skin.write_composite_fill(
                 w,
                 Composite::from_inline(&markdown),
-                self.width.into(),
+                self.width,
                 Alignment::Left,
             )?;
         }
@@ -128,8 +151,43 @@ impl View {
     }
 
     /// draw "executing...", the error code if any, or a blank line
-    pub fn draw_executing(&mut self) -> () {
+    pub fn draw_executing(&mut self, chunk: &str) {
         self.lines.push(ViewLine::ExecutionLine);
+        self.chunk_slots.insert(chunk.to_string(), self.lines.len());
+    }
+
+    /// keep the place of lines written later, e.g. the result of an
+    /// expected output block whose code chunk still runs
+    pub fn reserve_slot(&mut self, key: &str) {
+        self.chunk_slots.insert(key.to_string(), self.lines.len());
+    }
+
+    /// forget the place of the output of a finished code chunk
+    pub fn chunk_ends(&mut self, chunk: &str) {
+        self.chunk_slots.remove(chunk);
+    }
+
+    /// write an output line of a code chunk after its previous lines,
+    /// even when other lines were written since
+    pub fn write_chunk_output(
+        &mut self,
+        w: &mut W,
+        chunk: &str,
+        output: String,
+    ) -> anyhow::Result<()> {
+        let slot = match self.chunk_slots.get(chunk) {
+            Some(&slot) => slot,
+            None => return self.write_command_output(w, output),
+        };
+        self.lines.insert(slot, ViewLine::CodeOutput(output));
+        for other in self.chunk_slots.values_mut() {
+            if *other >= slot {
+                *other += 1;
+            }
+        }
+
+        self.scroll_to_bottom();
+        self.draw(w, Some(self.scroll as i32))
     }
 
     pub fn write_command_output(&mut self, w: &mut W, output: String) -> anyhow::Result<()> {
@@ -162,9 +220,9 @@ impl View {
             let fmt_text = Self::fill_line_width(&self.render_skin, text, width as usize);
 
             let mut text_view = TextView::from(&self.render_area, &fmt_text);
-            if lines_count.is_some() {
+            if let Some(lines_count) = lines_count {
                 text_view.scroll = self.scroll;
-                text_view.try_scroll_lines(lines_count.unwrap());
+                text_view.try_scroll_lines(lines_count);
                 self.scroll = text_view.scroll;
             } else {
                 text_view.try_scroll_lines(self.scroll as i32);
@@ -269,7 +327,7 @@ impl View {
         self.lines.len()
     }
     fn page_height(&self) -> usize {
-        self.height.max(3) as usize - 3
+        self.height.max(3) - 3
     }
 
     pub fn resize(&mut self, width: usize, height: usize) {
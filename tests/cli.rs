@@ -0,0 +1,61 @@
+//! runs of the markdown-gem binary without the terminal UI
+
+use std::fs;
+use std::process::{Command, Output};
+
+use uuid::Uuid;
+
+/// run `markdown-gem --ci` on a markdown file written in a new directory
+fn run_ci(md: &str) -> Output {
+    let dir = std::env::temp_dir().join(format!("gem-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let md_path = dir.join("README.md");
+    fs::write(&md_path, md).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_markdown-gem"))
+        .current_dir(&dir)
+        .arg("--ci")
+        .arg("-p")
+        .arg(&md_path)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+#[test]
+fn passing_run() {
+    let output = run_ci(
+        "# Title\n\n```sh {id=hello}\necho hello\n```\n\n```output {for=hello}\nhello\n```\n",
+    );
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "# Title\n\n```\necho hello\n```\n~~~~~~~~~\nhello\n~~~~~~~~~ exit status: 0\n\n```\nhello\n```\n~~~~~~~~~ output of hello matches line 7\n"
+    );
+}
+
+#[test]
+fn failing_run() {
+    let output = run_ci("```sh {id=broken}\necho before\nexit 3\n```\n\n```sh\necho after\n```\n");
+
+    assert_eq!(output.status.code(), Some(70));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("~~~~~~~~~\nbefore\n~~~~~~~~~ failed, exit status: 3\n"));
+    assert!(stdout.contains("Failure: 1 code chunk(s) failed, 0 error(s)\n"));
+    assert!(stdout.contains("README.md:1 `broken`"));
+}
+
+#[test]
+fn error_before_the_run() {
+    let output = run_ci(
+        "```sh {id=a, depends_on=[b]}\necho a\n```\n```sh {id=b, depends_on=[a]}\necho b\n```\n",
+    );
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!stdout(&output).contains("echo a"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("dependency cycle between code chunks"));
+}